@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zero2prod_practice::{domain::NameRules, routes::FormData};
+
+// What `web::Form` does with the body of `POST /subscriptions`.
+fuzz_target!(|body: &[u8]| {
+    if let Ok(form) = serde_urlencoded::from_bytes::<FormData>(body) {
+        let _ = form.new_subscriber(&NameRules::default());
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zero2prod_practice::domain::SubscriberEmail;
+
+fuzz_target!(|email: String| {
+    if let Ok(parsed) = SubscriberEmail::parse(email) {
+        // A normalized address is its own normal form.
+        let reparsed = SubscriberEmail::parse(parsed.as_ref().to_string())
+            .expect("A normalized email is valid.");
+        assert_eq!(parsed.as_ref(), reparsed.as_ref());
+        assert!(parsed.domain().is_ascii());
+    }
+});
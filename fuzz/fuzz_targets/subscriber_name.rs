@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zero2prod_practice::domain::{NameNormalization, NameRules, SubscriberName};
+
+fuzz_target!(|input: (String, bool, bool, u8)| {
+    let (name, strip_invisible, reject_confusables, normalization) = input;
+    let rules = NameRules {
+        strip_invisible,
+        reject_confusables,
+        normalization: match normalization % 3 {
+            0 => NameNormalization::None,
+            1 => NameNormalization::Nfc,
+            _ => NameNormalization::Nfkc,
+        },
+        ..NameRules::default()
+    };
+    if let Ok(parsed) = SubscriberName::parse_with_rules(name, &rules) {
+        assert_eq!(parsed.as_ref(), parsed.as_ref().trim());
+        assert!(!parsed.as_ref().is_empty());
+    }
+});
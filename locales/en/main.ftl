@@ -0,0 +1,111 @@
+## Subscribing
+
+# The name of the language, in itself.
+locale-name = English
+subscribe-title = Subscribe
+subscribe-name = Name
+subscribe-email = Email
+subscribe-language = Language
+subscribe-button = Subscribe
+confirmation-email-subject = Welcome!
+confirmation-email-body =
+    Welcome to our newsletter!<br />Click <a href="{ $link }">here</a> to confirm your subscription.
+list-unknown = { $list } is not a known list.
+locale-unsupported = { $locale } is not a supported language.
+
+## Confirming
+
+confirm-confirmed-title = Subscription confirmed
+confirm-confirmed-message = Thanks for confirming, the next issue is on its way to you.
+confirm-already-confirmed-title = Already confirmed
+confirm-already-confirmed-message = Your subscription was already confirmed, there is nothing more to do.
+confirm-expired-token-title = Link expired
+confirm-expired-token-message =
+    This confirmation link has expired. <a href="/subscriptions">Subscribe again</a> to get a new one.
+confirm-invalid-token-title = Invalid link
+confirm-invalid-token-message =
+    This confirmation link is not valid. Check that you copied all of it from the email we sent you.
+confirm-server-error-title = Something went wrong
+confirm-server-error-message = We could not confirm your subscription. Please try again in a few minutes.
+
+## Bot protection
+
+form-token-missing = The form token is missing.
+form-expired = The form is invalid or has expired, reload it.
+captcha-missing = The CAPTCHA is missing.
+captcha-failed = The CAPTCHA was not solved.
+
+## Email addresses
+
+email-empty = The email address is empty.
+email-invalid = { $email } is not a valid subscriber email.
+email-blocked-domain = Addresses at { $domain } cannot subscribe, use a permanent address.
+email-role-account = { $email } is a role account, use a personal address.
+email-mistyped = { $email } looks mistyped, did you mean { $suggestion }?
+email-undeliverable = { $domain } cannot receive email.
+
+## Names
+
+name-empty = The name is empty.
+name-too-short = The name is { $actual } characters long, it has to be at least { $min }.
+name-too-long = The name is { $actual } characters long, it can be at most { $max }.
+name-forbidden-character = The name cannot contain { $character }.
+name-mixed-scripts = The name mixes letters of different scripts that look alike.
+
+## Unsubscribing
+
+unsubscribe-title = Unsubscribe
+unsubscribe-button = Unsubscribe
+unsubscribed-title = Unsubscribed
+unsubscribed-message = You will no longer receive { $list }.
+
+## Preferences
+
+preferences-title = Your preferences
+preferences-name = Name
+preferences-email = Email
+preferences-cadence = Frequency
+preferences-cadence-immediate = Every issue
+preferences-cadence-daily = Daily digest
+preferences-cadence-weekly = Weekly digest
+preferences-language = Language
+preferences-lists = Topics
+preferences-save = Save
+preferences-saved = Your preferences have been saved.
+preferences-not-saved = Your preferences could not be saved.
+preferences-cadence-unsupported =
+    { $cadence } is not a supported frequency. Use either `immediate`, `daily` or `weekly`.
+preferences-email-unavailable = { $email } cannot be used.
+preferences-email-taken = { $email } is already subscribed.
+preferences-unknown-token = This link is not valid. Use the one from the latest email we sent you.
+preferences-server-error = Something went wrong. Please try again in a few minutes.
+email-change-email-subject = Confirm your new address
+email-change-email-body =
+    Please confirm your new address.<br />Click <a href="{ $link }">here</a> to receive our newsletter here from now on.
+email-change-confirmed-title = Email updated
+email-change-confirmed-message = We will write to { $email } from now on.
+email-change-failed-title = Email not updated
+
+## Issues and digests
+
+issue-view-in-browser = View in browser
+issue-manage-preferences = Manage your preferences
+issue-unsubscribe = Unsubscribe
+digest-subject = Your digest
+digest-subject-daily = Your daily digest
+digest-subject-weekly = Your weekly digest
+
+## Onboarding
+
+onboarding-welcome-subject = Welcome aboard
+onboarding-welcome-body =
+    <p>Hi { $name },</p><p>Thanks for confirming your subscription, we are glad to have you with us. The next issue will land in your inbox as soon as it is out.</p>
+onboarding-getting-started-subject = Getting the most out of the newsletter
+onboarding-getting-started-body =
+    <p>Hi { $name },</p><p>Add our sender address to your contacts so that issues do not end up in your spam folder.</p>
+onboarding-archive-subject = Catch up on past issues
+onboarding-archive-body =
+    <p>Hi { $name },</p><p>Every issue we sent so far is in <a href="{ $base_url }/newsletters">the archive</a>.</p>
+onboarding-preferences-subject = Make the newsletter yours
+onboarding-preferences-body =
+    <p>Hi { $name },</p><p>You can get a daily or weekly digest instead of every issue, and change your language, from the preferences linked below.</p>
@@ -0,0 +1,111 @@
+## Subscribing
+
+# The name of the language, in itself.
+locale-name = 繁體中文
+subscribe-title = 訂閱電子報
+subscribe-name = 名字
+subscribe-email = 電子郵件
+subscribe-language = 語言
+subscribe-button = 訂閱
+confirmation-email-subject = 歡迎訂閱！
+confirmation-email-body =
+    歡迎訂閱我們的電子報！<br />請點擊<a href="{ $link }">這裡</a>確認您的訂閱。
+list-unknown = { $list } 不是現有的訂閱清單。
+locale-unsupported = 目前不支援 { $locale } 語言。
+
+## Confirming
+
+confirm-confirmed-title = 訂閱已確認
+confirm-confirmed-message = 感謝您的確認，下一期電子報將會寄給您。
+confirm-already-confirmed-title = 訂閱早已確認
+confirm-already-confirmed-message = 您的訂閱先前已經確認過了，不需要再做任何事。
+confirm-expired-token-title = 連結已過期
+confirm-expired-token-message =
+    這個確認連結已經過期。請<a href="/subscriptions">重新訂閱</a>以取得新的連結。
+confirm-invalid-token-title = 連結無效
+confirm-invalid-token-message =
+    這個確認連結無效。請確認您已從我們寄出的電子郵件中完整複製連結。
+confirm-server-error-title = 發生錯誤
+confirm-server-error-message = 目前無法確認您的訂閱，請稍後再試一次。
+
+## Bot protection
+
+form-token-missing = 表單缺少驗證資訊。
+form-expired = 表單無效或已過期，請重新整理頁面。
+captcha-missing = 請完成驗證碼。
+captcha-failed = 驗證碼未通過。
+
+## Email addresses
+
+email-empty = 電子郵件地址是空的。
+email-invalid = { $email } 不是有效的電子郵件地址。
+email-blocked-domain = 無法使用 { $domain } 的地址訂閱，請改用長期使用的地址。
+email-role-account = { $email } 是共用帳號，請改用個人地址。
+email-mistyped = { $email } 似乎打錯了，您是要輸入 { $suggestion } 嗎？
+email-undeliverable = { $domain } 無法接收電子郵件。
+
+## Names
+
+name-empty = 名字是空的。
+name-too-short = 名字有 { $actual } 個字，至少需要 { $min } 個字。
+name-too-long = 名字有 { $actual } 個字，最多只能有 { $max } 個字。
+name-forbidden-character = 名字不能包含 { $character }。
+name-mixed-scripts = 名字混用了外觀相似的不同文字。
+
+## Unsubscribing
+
+unsubscribe-title = 取消訂閱
+unsubscribe-button = 取消訂閱
+unsubscribed-title = 已取消訂閱
+unsubscribed-message = 您將不會再收到{ $list }。
+
+## Preferences
+
+preferences-title = 訂閱偏好設定
+preferences-name = 名字
+preferences-email = 電子郵件
+preferences-cadence = 寄送頻率
+preferences-cadence-immediate = 每期寄送
+preferences-cadence-daily = 每日摘要
+preferences-cadence-weekly = 每週摘要
+preferences-language = 語言
+preferences-lists = 主題
+preferences-save = 儲存
+preferences-saved = 您的偏好設定已儲存。
+preferences-not-saved = 無法儲存您的偏好設定。
+preferences-cadence-unsupported =
+    目前不支援 { $cadence } 寄送頻率。請使用 `immediate`、`daily` 或 `weekly`。
+preferences-email-unavailable = 無法使用 { $email }。
+preferences-email-taken = { $email } 已經訂閱了。
+preferences-unknown-token = 這個連結無效。請使用我們最近一封電子郵件中的連結。
+preferences-server-error = 發生錯誤，請稍後再試一次。
+email-change-email-subject = 請確認您的新電子郵件地址
+email-change-email-body =
+    請確認您的新電子郵件地址。<br />請點擊<a href="{ $link }">這裡</a>，之後電子報將寄到這個地址。
+email-change-confirmed-title = 電子郵件已更新
+email-change-confirmed-message = 之後我們會寫信到 { $email }。
+email-change-failed-title = 電子郵件未更新
+
+## Issues and digests
+
+issue-view-in-browser = 在瀏覽器中檢視
+issue-manage-preferences = 管理訂閱設定
+issue-unsubscribe = 取消訂閱
+digest-subject = 您的電子報摘要
+digest-subject-daily = 您的每日摘要
+digest-subject-weekly = 您的每週摘要
+
+## Onboarding
+
+onboarding-welcome-subject = 歡迎加入
+onboarding-welcome-body =
+    <p>{ $name } 您好：</p><p>感謝您確認訂閱，很高興有您加入。下一期電子報出刊後會立即寄到您的信箱。</p>
+onboarding-getting-started-subject = 充分利用電子報
+onboarding-getting-started-body =
+    <p>{ $name } 您好：</p><p>請將我們的寄件地址加入聯絡人，以免電子報被歸到垃圾郵件。</p>
+onboarding-archive-subject = 回顧過去的電子報
+onboarding-archive-body =
+    <p>{ $name } 您好：</p><p>至今寄出的每一期電子報都收錄在<a href="{ $base_url }/newsletters">過刊</a>中。</p>
+onboarding-preferences-subject = 打造專屬於您的電子報
+onboarding-preferences-body =
+    <p>{ $name } 您好：</p><p>您可以改為每日或每週收到摘要，也可以更改語言，請使用下方的偏好設定連結。</p>
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE data_request_tokens(
+data_request_token TEXT NOT NULL,
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+kind TEXT NOT NULL,
+requested_at timestamptz NOT NULL,
+PRIMARY KEY (data_request_token)
+);
+
+CREATE TABLE suppressed_emails(
+email_hash TEXT NOT NULL,
+suppressed_at timestamptz NOT NULL,
+PRIMARY KEY (email_hash)
+);
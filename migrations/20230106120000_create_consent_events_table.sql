@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE consent_events(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+event_type TEXT NOT NULL,
+occurred_at timestamptz NOT NULL,
+ip_address TEXT NULL,
+user_agent TEXT NULL,
+source TEXT NULL,
+consent_text_version TEXT NOT NULL
+);
@@ -0,0 +1,33 @@
+-- Add migration script here
+BEGIN;
+CREATE TABLE lists(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+slug TEXT NOT NULL UNIQUE,
+name TEXT NOT NULL,
+created_at timestamptz NOT NULL
+);
+-- The implicit list everybody subscribed to so far
+INSERT INTO lists (id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+CREATE TABLE list_memberships(
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+list_id uuid NOT NULL
+REFERENCES lists (id),
+status TEXT NOT NULL,
+created_at timestamptz NOT NULL,
+PRIMARY KEY (subscriber_id, list_id)
+);
+INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+SELECT subscriptions.id, lists.id, subscriptions.status, subscriptions.subscribed_at
+FROM subscriptions, lists
+WHERE lists.slug = 'newsletter';
+
+-- Tokens confirm (and later unsubscribe from) a single list
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (id);
+UPDATE subscription_tokens
+SET list_id = (SELECT id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
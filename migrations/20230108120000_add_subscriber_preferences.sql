@@ -0,0 +1,22 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN delivery_cadence TEXT NOT NULL DEFAULT 'immediate';
+
+CREATE TABLE email_change_tokens(
+email_change_token TEXT NOT NULL,
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+new_email TEXT NOT NULL,
+requested_at timestamptz NOT NULL,
+PRIMARY KEY (email_change_token)
+);
+
+CREATE TABLE preference_changes(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+field TEXT NOT NULL,
+old_value TEXT NULL,
+new_value TEXT NULL,
+changed_at timestamptz NOT NULL
+);
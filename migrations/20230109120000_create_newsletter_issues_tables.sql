@@ -0,0 +1,29 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues(
+newsletter_issue_id uuid NOT NULL,
+PRIMARY KEY (newsletter_issue_id),
+title TEXT NOT NULL,
+content TEXT NOT NULL,
+status TEXT NOT NULL,
+send_at timestamptz NULL,
+timezone TEXT NULL,
+created_at timestamptz NOT NULL,
+published_at timestamptz NULL
+);
+
+CREATE TABLE newsletter_issue_lists(
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+list_id uuid NOT NULL
+REFERENCES lists (id),
+PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+CREATE TABLE issue_delivery_queue(
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+subscription_token TEXT NOT NULL,
+PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
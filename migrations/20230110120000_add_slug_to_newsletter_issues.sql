@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+CREATE UNIQUE INDEX newsletter_issues_slug_idx ON newsletter_issues (slug);
+CREATE INDEX newsletter_issues_published_at_idx ON newsletter_issues (published_at);
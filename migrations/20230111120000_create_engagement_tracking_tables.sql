@@ -0,0 +1,32 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE issue_links(
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+link_index INT NOT NULL,
+url TEXT NOT NULL,
+PRIMARY KEY (newsletter_issue_id, link_index)
+);
+
+CREATE TABLE issue_recipients(
+tracking_token TEXT NOT NULL,
+PRIMARY KEY (tracking_token),
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+sent_at timestamptz NOT NULL,
+UNIQUE (newsletter_issue_id, subscriber_id)
+);
+
+CREATE TABLE engagement_events(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+tracking_token TEXT NOT NULL
+REFERENCES issue_recipients (tracking_token),
+kind TEXT NOT NULL,
+link_index INT NULL,
+occurred_at timestamptz NOT NULL
+);
+CREATE INDEX engagement_events_tracking_token_idx ON engagement_events (tracking_token);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Delivered tasks are kept with their outcome to report on the progress of a send.
+ALTER TABLE issue_delivery_queue ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+ALTER TABLE issue_delivery_queue ADD COLUMN n_retries SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+ALTER TABLE issue_delivery_queue ADD COLUMN last_error TEXT NULL;
+ALTER TABLE issue_delivery_queue ADD COLUMN updated_at timestamptz NULL;
+CREATE INDEX issue_delivery_queue_pending_idx ON issue_delivery_queue (execute_after)
+WHERE status IN ('queued', 'retrying');
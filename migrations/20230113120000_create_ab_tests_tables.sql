@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TABLE ab_tests(
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+PRIMARY KEY (newsletter_issue_id),
+sample_percent SMALLINT NOT NULL,
+wait_minutes INT NOT NULL,
+decide_at timestamptz NULL,
+winning_variant SMALLINT NULL
+);
+
+CREATE TABLE subject_variants(
+newsletter_issue_id uuid NOT NULL
+REFERENCES ab_tests (newsletter_issue_id),
+variant_index SMALLINT NOT NULL,
+subject TEXT NOT NULL,
+PRIMARY KEY (newsletter_issue_id, variant_index)
+);
+
+-- The subject line variant each subscriber received.
+ALTER TABLE issue_delivery_queue ADD COLUMN variant_index SMALLINT NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN custom_attributes JSONB NOT NULL DEFAULT '{}';
+-- The filter expression the audience of an issue is narrowed down with.
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE digests(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+n_issues INT NOT NULL,
+sent_at timestamptz NOT NULL
+);
+CREATE INDEX digests_subscriber_id_idx ON digests (subscriber_id, sent_at);
+
+-- The digest that carried the issue to a subscriber on a digest cadence.
+ALTER TABLE issue_delivery_queue ADD COLUMN digest_id uuid NULL REFERENCES digests (id);
+CREATE INDEX issue_delivery_queue_digest_idx ON issue_delivery_queue (subscriber_id)
+WHERE status = 'digest';
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Submissions of the subscribe form, kept for a day to rate limit them.
+CREATE TABLE subscription_attempts(
+ip_address TEXT NULL,
+email_hash TEXT NOT NULL,
+attempted_at timestamptz NOT NULL
+);
+CREATE INDEX subscription_attempts_ip_address_idx ON subscription_attempts (ip_address, attempted_at);
+CREATE INDEX subscription_attempts_email_hash_idx ON subscription_attempts (email_hash, attempted_at);
+CREATE INDEX subscription_attempts_attempted_at_idx ON subscription_attempts (attempted_at);
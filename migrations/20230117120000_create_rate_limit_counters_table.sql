@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Fixed window request counters shared by all instances of the application.
+CREATE TABLE rate_limit_counters(
+key TEXT NOT NULL,
+PRIMARY KEY (key),
+window_start timestamptz NOT NULL,
+hits INTEGER NOT NULL,
+expires_at timestamptz NOT NULL
+);
+CREATE INDEX rate_limit_counters_expires_at_idx ON rate_limit_counters (expires_at);
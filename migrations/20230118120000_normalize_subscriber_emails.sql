@@ -0,0 +1,40 @@
+-- Add migration script here
+-- Addresses are stored normalized, the way `SubscriberEmail::parse` does it,
+-- next to the form they were typed in: NFC, with the domain in its ASCII form.
+-- Only the domain is case-insensitive, the local part is kept as is.
+ALTER TABLE subscriptions ADD COLUMN email_original TEXT NULL;
+UPDATE subscriptions SET email_original = email;
+ALTER TABLE subscriptions ALTER COLUMN email_original SET NOT NULL;
+
+-- Internationalized domains need punycode, which the database cannot compute.
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM subscriptions
+        WHERE substring(email from '@([^@]*)$') ~ '[^[:ascii:]]'
+    ) THEN
+        RAISE EXCEPTION 'Some subscriber addresses have an internationalized domain: convert them to punycode before migrating.';
+    END IF;
+END $$;
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+-- NFC leaves ASCII as is: only the other addresses need normalizing.
+UPDATE subscriptions SET email = normalize(email, NFC) WHERE email ~ '[^[:ascii:]]';
+UPDATE subscriptions
+SET email = substring(email from '^(.*)@') || '@' || lower(substring(email from '@([^@]*)$'))
+WHERE email LIKE '%@%';
+
+-- Subscribers stored twice have to be merged by hand, nothing is deleted here.
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(email, ', ') INTO duplicates
+    FROM (
+        SELECT email FROM subscriptions GROUP BY email HAVING COUNT(*) > 1
+    ) AS duplicate_emails;
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'Several subscribers have the same normalized address: %. Merge them before migrating.', duplicates;
+    END IF;
+END $$;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_email_key UNIQUE (email);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The language subscribers get their emails and pages in, as a tag such as
+-- `zh-TW`.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
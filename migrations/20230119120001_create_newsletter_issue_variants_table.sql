@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Translations of an issue. Subscribers whose locale has none get the issue
+-- as written.
+CREATE TABLE newsletter_issue_variants(
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+locale TEXT NOT NULL,
+title TEXT NOT NULL,
+content TEXT NOT NULL,
+PRIMARY KEY (newsletter_issue_id, locale)
+);
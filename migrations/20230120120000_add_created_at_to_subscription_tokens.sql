@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Confirmation links expire, so tokens need to know when they were issued.
+-- Those issued before this migration count from now.
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE onboarding_progress(
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+PRIMARY KEY (subscriber_id),
+-- Delays of the steps count from it.
+started_at timestamptz NOT NULL,
+-- Index of the next step of the sequence to send.
+next_step INT NOT NULL,
+-- active, completed or stopped
+status TEXT NOT NULL,
+updated_at timestamptz NOT NULL
+);
+CREATE INDEX onboarding_progress_active_idx ON onboarding_progress (started_at)
+WHERE status = 'active';
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Theoretical arrival times of the rate limit rules using GCRA, shared by
+-- all instances of the application.
+CREATE TABLE rate_limit_arrivals(
+key TEXT NOT NULL,
+PRIMARY KEY (key),
+theoretical_arrival timestamptz NOT NULL
+);
+CREATE INDEX rate_limit_arrivals_theoretical_arrival_idx ON rate_limit_arrivals (theoretical_arrival);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Translations of an issue have links of their own. `locale` is the variant
+-- the links come from, and the one each recipient got: empty for the issue
+-- as written.
+ALTER TABLE issue_links ADD COLUMN locale TEXT NOT NULL DEFAULT '';
+ALTER TABLE issue_links DROP CONSTRAINT issue_links_pkey;
+ALTER TABLE issue_links ADD PRIMARY KEY (newsletter_issue_id, locale, link_index);
+ALTER TABLE issue_recipients ADD COLUMN locale TEXT NOT NULL DEFAULT '';
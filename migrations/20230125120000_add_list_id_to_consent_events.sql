@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Consent is given list by list. Events recorded before this column existed
+-- do not say which list they were for.
+ALTER TABLE consent_events ADD COLUMN list_id uuid NULL REFERENCES lists (id);
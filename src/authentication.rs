@@ -0,0 +1,62 @@
+use actix_web::{
+    dev::Payload, http::header, web, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+pub struct AdminToken(pub Secret<String>);
+
+/// Guards the editor endpoints: requests have to carry the configured admin
+/// token as a bearer token.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = AuthError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        std::future::ready(authenticate(req))
+    }
+}
+
+fn authenticate(req: &HttpRequest) -> Result<Admin, AuthError> {
+    let expected = req
+        .app_data::<web::Data<AdminToken>>()
+        .expect("The admin token is not registered as application data.");
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthError)?;
+    // Comparing digests keeps the comparison time independent of how much of
+    // the token was guessed right.
+    if Sha256::digest(provided.as_bytes()) == Sha256::digest(expected.0.expose_secret().as_bytes())
+    {
+        Ok(Admin)
+    } else {
+        Err(AuthError)
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthError;
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Missing or invalid admin token.")
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, r#"Bearer realm="admin""#))
+            .finish()
+    }
+}
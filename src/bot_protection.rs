@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures_util::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::configuration::SubscribeProtectionSettings;
+
+/// What the subscribe form is checked against before anyone gets emailed.
+pub struct SubscribeProtection {
+    pub settings: SubscribeProtectionSettings,
+    pub captcha_verifier: Option<Arc<dyn CaptchaVerifier>>,
+}
+
+/// Checks the response a CAPTCHA widget put in the subscribe form.
+pub trait CaptchaVerifier: Send + Sync {
+    fn verify<'a>(
+        &'a self,
+        captcha_response: &'a str,
+        remote_ip: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, reqwest::Error>>;
+}
+
+/// A verifier for the `siteverify` APIs shared by hCaptcha, reCAPTCHA and
+/// Turnstile.
+pub struct HttpCaptchaVerifier {
+    http_client: reqwest::Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+impl HttpCaptchaVerifier {
+    pub fn new(verify_url: String, secret: Secret<String>, timeout: std::time::Duration) -> Self {
+        let http_client = reqwest::Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            verify_url,
+            secret,
+        }
+    }
+}
+
+impl CaptchaVerifier for HttpCaptchaVerifier {
+    fn verify<'a>(
+        &'a self,
+        captcha_response: &'a str,
+        remote_ip: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, reqwest::Error>> {
+        Box::pin(async move {
+            let mut form = vec![
+                ("secret", self.secret.expose_secret().as_str()),
+                ("response", captcha_response),
+            ];
+            if let Some(remote_ip) = remote_ip {
+                form.push(("remoteip", remote_ip));
+            }
+            let response: SiteVerifyResponse = self
+                .http_client
+                .post(&self.verify_url)
+                .form(&form)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            Ok(response.success)
+        })
+    }
+}
+
+/// Why a form token was not accepted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormTokenError {
+    Invalid,
+    Expired,
+    /// Submitted faster than a person could fill in the form.
+    TooFast,
+}
+
+/// A token embedded in the subscribe form recording when it was served,
+/// `{unix timestamp}.{hex HMAC of the timestamp}`.
+pub fn issue_form_token(secret: &Secret<String>, issued_at: DateTime<Utc>) -> String {
+    let timestamp = issued_at.timestamp().to_string();
+    let signature = form_token_mac(secret, &timestamp)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("{}.{}", timestamp, signature)
+}
+
+pub fn verify_form_token(
+    secret: &Secret<String>,
+    form_token: &str,
+    min_age: Duration,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> Result<(), FormTokenError> {
+    let (timestamp, signature) = form_token.split_once('.').ok_or(FormTokenError::Invalid)?;
+    let signature = decode_hex(signature).ok_or(FormTokenError::Invalid)?;
+    form_token_mac(secret, timestamp)
+        .verify_slice(&signature)
+        .map_err(|_| FormTokenError::Invalid)?;
+    let issued_at = timestamp
+        .parse()
+        .ok()
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+        .ok_or(FormTokenError::Invalid)?;
+    let age = now - issued_at;
+    if age < min_age {
+        Err(FormTokenError::TooFast)
+    } else if age > max_age {
+        Err(FormTokenError::Expired)
+    } else {
+        Ok(())
+    }
+}
+
+fn form_token_mac(secret: &Secret<String>, timestamp: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length.");
+    mac.update(timestamp.as_bytes());
+    mac
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_form_token, verify_form_token, FormTokenError};
+    use chrono::{Duration, Utc};
+    use claims::{assert_err_eq, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("form-secret".into())
+    }
+
+    #[test]
+    fn form_tokens_are_accepted_within_their_time_window() {
+        let now = Utc::now();
+        let token = issue_form_token(&secret(), now - Duration::seconds(10));
+        let check = |now| {
+            verify_form_token(
+                &secret(),
+                &token,
+                Duration::seconds(3),
+                Duration::hours(1),
+                now,
+            )
+        };
+
+        assert_ok!(check(now));
+        assert_err_eq!(check(now - Duration::seconds(8)), FormTokenError::TooFast);
+        assert_err_eq!(check(now + Duration::hours(2)), FormTokenError::Expired);
+    }
+
+    #[test]
+    fn forged_form_tokens_are_rejected() {
+        let now = Utc::now();
+        let token = issue_form_token(&Secret::new("another-secret".into()), now);
+        let (_, signature) = token.split_once('.').unwrap();
+        let backdated = format!("{}.{}", (now - Duration::hours(1)).timestamp(), signature);
+
+        for token in [
+            token.as_str(),
+            backdated.as_str(),
+            "",
+            "1672531200",
+            "1672531200.zz",
+        ] {
+            assert_err_eq!(
+                verify_form_token(&secret(), token, Duration::zero(), Duration::days(1), now),
+                FormTokenError::Invalid
+            );
+        }
+    }
+}
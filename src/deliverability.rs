@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use trust_dns_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+
+use crate::domain::{EmailError, SubscriberEmail};
+
+/// Looks up whether a domain can receive email.
+pub trait DomainResolver: Send + Sync {
+    /// Whether the domain has MX records or, failing that, an address.
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, String>>;
+}
+
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    /// A resolver using the nameservers of the system.
+    pub fn from_system_conf() -> Result<Self, String> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| e.to_string())?;
+        Ok(Self { resolver })
+    }
+}
+
+impl DomainResolver for DnsResolver {
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, String>> {
+        // A trailing dot keeps the system search domains out of the lookup.
+        let fqdn = format!("{}.", domain);
+        Box::pin(async move {
+            match self.resolver.mx_lookup(fqdn.as_str()).await {
+                Ok(mx) if mx.iter().next().is_some() => return Ok(true),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+            // Without MX records, mail goes to the address of the domain.
+            match self.resolver.lookup_ip(fqdn.as_str()).await {
+                Ok(ips) => Ok(ips.iter().next().is_some()),
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+                Err(e) => Err(e.to_string()),
+            }
+        })
+    }
+}
+
+/// Catches addresses confirmation emails would be wasted on before sending
+/// them.
+pub struct DeliverabilityCheck {
+    pub suggest_typos: bool,
+    pub resolver: Option<Arc<dyn DomainResolver>>,
+    pub timeout: Duration,
+}
+
+impl DeliverabilityCheck {
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), EmailError> {
+        let suggestion = if self.suggest_typos {
+            suggest_domain(email.domain())
+        } else {
+            None
+        };
+        if let Some(suggestion) = suggestion {
+            return Err(EmailError::Mistyped {
+                email: email.original().to_string(),
+                suggestion: format!("{}@{}", email.local_part(), suggestion),
+            });
+        }
+        let resolver = match &self.resolver {
+            Some(resolver) => resolver,
+            None => return Ok(()),
+        };
+        // Whether DNS is slow or down, it is not the subscriber's fault: let
+        // them through.
+        match tokio::time::timeout(self.timeout, resolver.accepts_mail(email.domain())).await {
+            Ok(Ok(true)) => Ok(()),
+            Ok(Ok(false)) => Err(EmailError::Undeliverable {
+                domain: email.domain().to_string(),
+            }),
+            Ok(Err(e)) => {
+                tracing::warn!(error.message = %e, "Failed to look up the domain of an email.");
+                Ok(())
+            }
+            Err(_) => {
+                tracing::warn!("Timed out looking up the domain of an email.");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Domains of the most common mailbox providers, which typos are checked
+/// against.
+const COMMON_DOMAINS: &[&str] = &[
+    "aol.com",
+    "comcast.net",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "gmx.net",
+    "googlemail.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.fr",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "qq.com",
+    "web.de",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.fr",
+    "yandex.ru",
+];
+
+/// A common domain the given one is a likely typo of. Short domains are only
+/// a single edit apart from many real ones, so they have to be closer.
+pub fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if COMMON_DOMAINS.contains(&domain) {
+        return None;
+    }
+    COMMON_DOMAINS
+        .iter()
+        .map(|common| (edit_distance(domain, common), *common))
+        .filter(|(distance, common)| *distance <= if common.len() <= 7 { 1 } else { 2 })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, common)| common)
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// swaps of adjacent characters each count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest_domain};
+
+    #[test]
+    fn common_typos_get_a_suggestion() {
+        assert_eq!(suggest_domain("gmial.com"), Some("gmail.com"));
+        assert_eq!(suggest_domain("hotmial.com"), Some("hotmail.com"));
+        assert_eq!(suggest_domain("yaho.com"), Some("yahoo.com"));
+        assert_eq!(suggest_domain("outlok.com"), Some("outlook.com"));
+    }
+
+    #[test]
+    fn known_and_unrelated_domains_get_no_suggestion() {
+        assert_eq!(suggest_domain("gmail.com"), None);
+        assert_eq!(suggest_domain("mail.com"), None);
+        assert_eq!(suggest_domain("zero2prod.example"), None);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+    }
+}
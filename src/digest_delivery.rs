@@ -0,0 +1,314 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{DeliveryCadence, DeliveryStatus, SubscriberEmail},
+    email_client::EmailClient,
+    i18n::Locale,
+    issue_delivery_worker::render_issue,
+    startup::get_connection_pool,
+    utils::html_escape,
+};
+
+pub async fn run_digests_until_stopped(configuration: Settings) -> Result<(), std::io::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration
+        .email_client
+        .client()
+        .map_err(std::io::Error::other)?;
+    digest_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+    )
+    .await
+}
+
+async fn digest_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), std::io::Error> {
+    loop {
+        if let Err(e) = send_due_digests(&pool, &email_client, &base_url, Utc::now()).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send digests.",
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Send a digest to every subscriber on a digest cadence whose period is
+/// over and who has issues waiting for them. Returns how many digests were
+/// sent. Issues
+/// of a list the subscriber has left since are not sent anymore.
+#[tracing::instrument(skip(pool, email_client, base_url), err)]
+pub async fn send_due_digests(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    let candidates = sqlx::query!(
+        r#"
+SELECT
+    subscriptions.id AS subscriber_id,
+    subscriptions.delivery_cadence,
+    (SELECT MAX(sent_at) FROM digests WHERE digests.subscriber_id = subscriptions.id) AS last_digest_at,
+    MIN(newsletter_issues.published_at) AS "oldest_pending_at!"
+FROM issue_delivery_queue
+JOIN subscriptions ON subscriptions.id = issue_delivery_queue.subscriber_id
+JOIN newsletter_issues
+    ON newsletter_issues.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+WHERE issue_delivery_queue.status = 'digest'
+    AND newsletter_issues.status IN ('sending', 'sent')
+    AND EXISTS (
+        SELECT 1 FROM newsletter_issue_lists
+        JOIN list_memberships ON list_memberships.list_id = newsletter_issue_lists.list_id
+        WHERE newsletter_issue_lists.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+            AND list_memberships.subscriber_id = issue_delivery_queue.subscriber_id
+            AND list_memberships.status = 'confirmed'
+    )
+GROUP BY subscriptions.id
+"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut n_sent = 0;
+    for candidate in candidates {
+        let cadence = DeliveryCadence::parse(candidate.delivery_cadence)
+            .expect("A stored delivery cadence is always valid.");
+        if !cadence.is_digest_due(candidate.last_digest_at, candidate.oldest_pending_at, now) {
+            continue;
+        }
+        if send_digest(
+            pool,
+            email_client,
+            base_url,
+            candidate.subscriber_id,
+            cadence,
+            now,
+        )
+        .await?
+        {
+            n_sent += 1;
+        }
+    }
+    Ok(n_sent)
+}
+
+struct DigestItem {
+    newsletter_issue_id: Uuid,
+    title: String,
+    content: String,
+    slug: Option<String>,
+    subscription_token: String,
+    email: String,
+    locale: String,
+}
+
+/// Collect the issues waiting for the subscriber into one email. If it cannot
+/// be sent, they are left waiting for the next run.
+#[tracing::instrument(skip(pool, email_client, base_url))]
+async fn send_digest(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    subscriber_id: Uuid,
+    cadence: DeliveryCadence,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let items = sqlx::query_as!(
+        DigestItem,
+        r#"
+SELECT
+    issue_delivery_queue.newsletter_issue_id,
+    COALESCE(newsletter_issue_variants.title, newsletter_issues.title) AS "title!",
+    COALESCE(newsletter_issue_variants.content, newsletter_issues.content) AS "content!",
+    newsletter_issues.slug,
+    issue_delivery_queue.subscription_token,
+    subscriptions.email,
+    subscriptions.locale
+FROM issue_delivery_queue
+JOIN subscriptions ON subscriptions.id = issue_delivery_queue.subscriber_id
+JOIN newsletter_issues
+    ON newsletter_issues.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+LEFT JOIN newsletter_issue_variants
+    ON newsletter_issue_variants.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+    AND newsletter_issue_variants.locale = subscriptions.locale
+WHERE issue_delivery_queue.subscriber_id = $1
+    AND issue_delivery_queue.status = 'digest'
+    AND newsletter_issues.status IN ('sending', 'sent')
+    AND EXISTS (
+        SELECT 1 FROM newsletter_issue_lists
+        JOIN list_memberships ON list_memberships.list_id = newsletter_issue_lists.list_id
+        WHERE newsletter_issue_lists.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+            AND list_memberships.subscriber_id = issue_delivery_queue.subscriber_id
+            AND list_memberships.status = 'confirmed'
+    )
+ORDER BY newsletter_issues.published_at
+FOR UPDATE OF issue_delivery_queue
+SKIP LOCKED
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    // Another run got to this subscriber first.
+    let last_item = match items.last() {
+        Some(item) => item,
+        None => return Ok(false),
+    };
+    let newsletter_issue_ids: Vec<Uuid> = items.iter().map(|i| i.newsletter_issue_id).collect();
+
+    let email = match SubscriberEmail::parse(last_item.email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a digest subscriber. Their stored contact details are invalid",
+            );
+            sqlx::query!(
+                r#"
+UPDATE issue_delivery_queue SET status = $3, last_error = $4, updated_at = $5
+WHERE subscriber_id = $1 AND newsletter_issue_id = ANY($2)
+"#,
+                subscriber_id,
+                &newsletter_issue_ids,
+                DeliveryStatus::Bounced.as_str(),
+                e.to_string(),
+                now
+            )
+            .execute(&mut transaction)
+            .await?;
+            transaction.commit().await?;
+            return Ok(false);
+        }
+    };
+    let locale = Locale::from_stored(&last_item.locale);
+    let subject = locale.message(
+        match cadence {
+            DeliveryCadence::Daily => "digest-subject-daily",
+            DeliveryCadence::Weekly => "digest-subject-weekly",
+            DeliveryCadence::Immediate => "digest-subject",
+        },
+        &[],
+    );
+    // The most recent membership is the one the footer links manage.
+    let html_body = render_digest(&items, base_url, &last_item.subscription_token, locale);
+    if let Err(e) = email_client
+        .send_email(email, &subject, "text/html", &html_body)
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to deliver a digest.",
+        );
+        return Ok(false);
+    }
+
+    let digest_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO digests (id, subscriber_id, n_issues, sent_at)
+VALUES ($1, $2, $3, $4)
+"#,
+        digest_id,
+        subscriber_id,
+        items.len() as i32,
+        now
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue SET status = $3, digest_id = $4, updated_at = $5
+WHERE subscriber_id = $1 AND newsletter_issue_id = ANY($2)
+"#,
+        subscriber_id,
+        &newsletter_issue_ids,
+        DeliveryStatus::Sent.as_str(),
+        digest_id,
+        now
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// One section per issue, oldest first, each linking to its web version.
+fn render_digest(
+    items: &[DigestItem],
+    base_url: &str,
+    subscription_token: &str,
+    locale: Locale,
+) -> String {
+    let sections: String = items
+        .iter()
+        .map(|item| {
+            let web_version = match &item.slug {
+                Some(slug) => format!(
+                    "<a href=\"{}/newsletters/{}\">{}</a>",
+                    base_url,
+                    slug,
+                    locale.message("issue-view-in-browser", &[])
+                ),
+                None => String::new(),
+            };
+            format!(
+                "<h2>{}</h2>{}{}<hr />",
+                html_escape(&item.title),
+                item.content,
+                web_version
+            )
+        })
+        .collect();
+    render_issue(&sections, base_url, None, subscription_token, locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_digest, DigestItem};
+    use crate::i18n::Locale;
+    use uuid::Uuid;
+
+    fn item(title: &str, slug: &str) -> DigestItem {
+        DigestItem {
+            newsletter_issue_id: Uuid::new_v4(),
+            title: title.into(),
+            content: format!("<p>{} content</p>", title),
+            slug: Some(slug.into()),
+            subscription_token: "token".into(),
+            email: "ursula_le_guin@gmail.com".into(),
+            locale: "en".into(),
+        }
+    }
+
+    #[test]
+    fn a_digest_has_a_section_per_issue() {
+        let digest = render_digest(
+            &[item("First", "first"), item("Second & last", "second")],
+            "https://app.test",
+            "token",
+            Locale::En,
+        );
+
+        let first = digest.find("<h2>First</h2><p>First content</p>").unwrap();
+        let second = digest.find("<h2>Second &amp; last</h2>").unwrap();
+        assert!(first < second);
+        assert!(digest.contains(r#"<a href="https://app.test/newsletters/second">"#));
+        assert!(digest.contains("/subscriptions/unsubscribe?subscription_token=token"));
+    }
+}
@@ -0,0 +1,154 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Subject lines to try out on a sample of an issue's audience before the
+/// rest of it gets the one with the best open rate.
+#[derive(Debug)]
+pub struct AbTest {
+    subjects: Vec<String>,
+    sample_percent: i16,
+    wait_minutes: i32,
+}
+
+/// How a subject line did with the part of the sample that received it.
+#[derive(Debug)]
+pub struct VariantResult {
+    pub variant_index: i16,
+    pub sent: i64,
+    pub opens: i64,
+}
+
+impl VariantResult {
+    pub fn open_rate(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.opens as f64 / self.sent as f64
+        }
+    }
+}
+
+impl AbTest {
+    const MAX_VARIANTS: usize = 5;
+    const MAX_WAIT_MINUTES: i32 = 7 * 24 * 60;
+
+    pub fn parse(
+        subjects: Vec<String>,
+        sample_percent: i16,
+        wait_minutes: i32,
+    ) -> Result<Self, String> {
+        if subjects.len() < 2 || subjects.len() > Self::MAX_VARIANTS {
+            return Err(format!(
+                "An A/B test needs between 2 and {} subject lines.",
+                Self::MAX_VARIANTS
+            ));
+        }
+        if let Some(subject) = subjects
+            .iter()
+            .find(|s| s.trim().is_empty() || s.graphemes(true).count() > 256)
+        {
+            return Err(format!("{} is not a valid subject line.", subject));
+        }
+        if (1..subjects.len()).any(|i| subjects[..i].contains(&subjects[i])) {
+            return Err("The subject lines of an A/B test have to differ.".into());
+        }
+        if !(1..=50).contains(&sample_percent) {
+            return Err("The sample has to be between 1% and 50% of the audience.".into());
+        }
+        if !(1..=Self::MAX_WAIT_MINUTES).contains(&wait_minutes) {
+            return Err(format!(
+                "The test has to run between 1 and {} minutes.",
+                Self::MAX_WAIT_MINUTES
+            ));
+        }
+        Ok(Self {
+            subjects,
+            sample_percent,
+            wait_minutes,
+        })
+    }
+
+    pub fn subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    pub fn sample_percent(&self) -> i16 {
+        self.sample_percent
+    }
+
+    pub fn wait_minutes(&self) -> i32 {
+        self.wait_minutes
+    }
+
+    /// How many subscribers of the audience take part in the test: the sample
+    /// percentage, rounded up, but at least one per variant.
+    pub fn sample_size(sample_percent: i16, n_variants: i64, audience: i64) -> i64 {
+        let sample = (audience * sample_percent as i64 + 99) / 100;
+        sample.max(n_variants).min(audience)
+    }
+
+    /// The variant with the highest open rate. Ties go to the earliest one.
+    pub fn pick_winner(results: &[VariantResult]) -> Option<i16> {
+        results
+            .iter()
+            .fold(None, |best: Option<&VariantResult>, result| match best {
+                Some(best) if best.open_rate() >= result.open_rate() => Some(best),
+                _ => Some(result),
+            })
+            .map(|winner| winner.variant_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbTest, VariantResult};
+    use claims::{assert_err, assert_ok};
+
+    fn subjects(subjects: &[&str]) -> Vec<String> {
+        subjects.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn a_test_needs_at_least_two_distinct_subjects() {
+        assert_ok!(AbTest::parse(subjects(&["A", "B"]), 20, 60));
+        assert_err!(AbTest::parse(subjects(&["A"]), 20, 60));
+        assert_err!(AbTest::parse(subjects(&["A", "A"]), 20, 60));
+        assert_err!(AbTest::parse(subjects(&["A", " "]), 20, 60));
+    }
+
+    #[test]
+    fn the_sample_and_the_wait_are_bounded() {
+        assert_err!(AbTest::parse(subjects(&["A", "B"]), 0, 60));
+        assert_err!(AbTest::parse(subjects(&["A", "B"]), 51, 60));
+        assert_err!(AbTest::parse(subjects(&["A", "B"]), 20, 0));
+    }
+
+    #[test]
+    fn the_sample_has_at_least_one_subscriber_per_variant() {
+        assert_eq!(AbTest::sample_size(10, 2, 1000), 100);
+        assert_eq!(AbTest::sample_size(10, 3, 5), 3);
+        assert_eq!(AbTest::sample_size(10, 3, 2), 2);
+    }
+
+    #[test]
+    fn the_variant_with_the_best_open_rate_wins() {
+        let results = vec![
+            VariantResult {
+                variant_index: 0,
+                sent: 10,
+                opens: 2,
+            },
+            VariantResult {
+                variant_index: 1,
+                sent: 10,
+                opens: 5,
+            },
+            VariantResult {
+                variant_index: 2,
+                sent: 10,
+                opens: 5,
+            },
+        ];
+        assert_eq!(AbTest::pick_winner(&results), Some(1));
+        assert_eq!(AbTest::pick_winner(&[]), None);
+    }
+}
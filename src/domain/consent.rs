@@ -0,0 +1,24 @@
+/// What a subscriber agreed to and where the agreement came from, as
+/// recorded for every step of the double opt-in.
+#[derive(Debug)]
+pub struct ConsentMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    pub consent_text_version: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ConsentEventType {
+    Requested,
+    Confirmed,
+}
+
+impl ConsentEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventType::Requested => "requested",
+            ConsentEventType::Confirmed => "confirmed",
+        }
+    }
+}
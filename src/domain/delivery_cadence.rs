@@ -0,0 +1,99 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// How often a subscriber wants to hear from us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryCadence {
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DeliveryCadence {
+    pub fn parse(cadence: String) -> Result<Self, String> {
+        match cadence.as_str() {
+            "immediate" => Ok(Self::Immediate),
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            other => Err(format!(
+                "{} is not a supported delivery cadence. Use either `immediate`, `daily` or `weekly`.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryCadence::Immediate => "immediate",
+            DeliveryCadence::Daily => "daily",
+            DeliveryCadence::Weekly => "weekly",
+        }
+    }
+
+    /// How long issues are collected before going out together in a digest.
+    /// Subscribers on the immediate cadence get every issue on its own.
+    pub fn digest_period(&self) -> Option<Duration> {
+        match self {
+            DeliveryCadence::Immediate => None,
+            DeliveryCadence::Daily => Some(Duration::days(1)),
+            DeliveryCadence::Weekly => Some(Duration::weeks(1)),
+        }
+    }
+
+    /// Whether a digest is due, given when the last one went out and when the
+    /// oldest of the issues waiting for the next one was published.
+    pub fn is_digest_due(
+        &self,
+        last_digest_at: Option<DateTime<Utc>>,
+        oldest_pending_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match self.digest_period() {
+            Some(period) => now - last_digest_at.unwrap_or(oldest_pending_at) >= period,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryCadence;
+    use chrono::{Duration, TimeZone, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn supported_cadences_are_parsed() {
+        for cadence in [
+            DeliveryCadence::Immediate,
+            DeliveryCadence::Daily,
+            DeliveryCadence::Weekly,
+        ] {
+            assert_ok_eq!(DeliveryCadence::parse(cadence.as_str().into()), cadence);
+        }
+    }
+
+    #[test]
+    fn unknown_cadences_are_rejected() {
+        assert_err!(DeliveryCadence::parse("monthly".into()));
+    }
+
+    #[test]
+    fn digests_go_out_once_per_period() {
+        let published_at = Utc.with_ymd_and_hms(2023, 1, 2, 9, 0, 0).unwrap();
+        let weekly = DeliveryCadence::Weekly;
+
+        assert!(!weekly.is_digest_due(None, published_at, published_at + Duration::days(6)));
+        assert!(weekly.is_digest_due(None, published_at, published_at + Duration::days(7)));
+        // The period runs from the last digest rather than the oldest issue.
+        let last_digest_at = published_at + Duration::days(3);
+        assert!(!weekly.is_digest_due(
+            Some(last_digest_at),
+            published_at,
+            published_at + Duration::days(7)
+        ));
+        assert!(DeliveryCadence::Daily.is_digest_due(
+            Some(last_digest_at),
+            published_at,
+            last_digest_at + Duration::days(1)
+        ));
+    }
+}
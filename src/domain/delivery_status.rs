@@ -0,0 +1,85 @@
+/// The outcome of delivering an issue to one subscriber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    Failed,
+    /// The email provider rejected the recipient; retrying will not help.
+    Bounced,
+    Retrying,
+    /// The issue got cancelled before this delivery was attempted.
+    Cancelled,
+    /// Waiting for the A/B test of the issue to pick a subject line.
+    Held,
+    /// Waiting to go out with the other issues of the subscriber's digest.
+    Digest,
+}
+
+impl DeliveryStatus {
+    /// How many times a failed delivery is retried before giving up.
+    pub const MAX_RETRIES: i16 = 3;
+
+    pub fn parse(status: String) -> Result<Self, String> {
+        match status.as_str() {
+            "queued" => Ok(Self::Queued),
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            "bounced" => Ok(Self::Bounced),
+            "retrying" => Ok(Self::Retrying),
+            "cancelled" => Ok(Self::Cancelled),
+            "held" => Ok(Self::Held),
+            "digest" => Ok(Self::Digest),
+            other => Err(format!("{} is not a known delivery status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+            DeliveryStatus::Retrying => "retrying",
+            DeliveryStatus::Cancelled => "cancelled",
+            DeliveryStatus::Held => "held",
+            DeliveryStatus::Digest => "digest",
+        }
+    }
+
+    /// Where a delivery goes after an attempt failed, given how many times it
+    /// had already been retried.
+    pub fn after_failure(n_retries: i16, is_permanent: bool) -> Self {
+        if is_permanent {
+            DeliveryStatus::Bounced
+        } else if n_retries < Self::MAX_RETRIES {
+            DeliveryStatus::Retrying
+        } else {
+            DeliveryStatus::Failed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryStatus;
+
+    #[test]
+    fn transient_failures_are_retried_a_limited_number_of_times() {
+        assert_eq!(
+            DeliveryStatus::after_failure(0, false),
+            DeliveryStatus::Retrying
+        );
+        assert_eq!(
+            DeliveryStatus::after_failure(DeliveryStatus::MAX_RETRIES, false),
+            DeliveryStatus::Failed
+        );
+    }
+
+    #[test]
+    fn rejected_recipients_are_not_retried() {
+        assert_eq!(
+            DeliveryStatus::after_failure(0, true),
+            DeliveryStatus::Bounced
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use crate::domain::{EmailError, SubscriberEmail};
+
+const DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// Mailboxes belonging to a role rather than a person, which should not be
+/// signed up to a newsletter.
+const ROLE_ACCOUNTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "hostmaster",
+    "mailer-daemon",
+    "no-reply",
+    "noreply",
+    "postmaster",
+    "root",
+    "webmaster",
+];
+
+/// Which addresses we accept subscriptions from, on top of them being valid.
+#[derive(Debug, Default)]
+pub struct EmailPolicy {
+    blocked_domains: HashSet<String>,
+    reject_role_accounts: bool,
+}
+
+impl EmailPolicy {
+    pub fn new(
+        block_disposable_domains: bool,
+        blocked_domains: &[String],
+        reject_role_accounts: bool,
+    ) -> Self {
+        let disposable_domains = DISPOSABLE_DOMAINS
+            .lines()
+            .filter(|_| block_disposable_domains)
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let blocked_domains = disposable_domains
+            .chain(blocked_domains.iter().map(|d| d.trim()))
+            .map(|d| d.to_lowercase())
+            .collect();
+        Self {
+            blocked_domains,
+            reject_role_accounts,
+        }
+    }
+
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), EmailError> {
+        let domain = email.domain();
+        // Subdomains of a blocked domain are blocked as well.
+        let is_blocked = domain
+            .match_indices('.')
+            .map(|(i, _)| &domain[i + 1..])
+            .chain(std::iter::once(domain))
+            .any(|d| self.blocked_domains.contains(d));
+        if is_blocked {
+            return Err(EmailError::BlockedDomain {
+                domain: domain.to_string(),
+            });
+        }
+        // `noreply+news@` is as much of a role account as `noreply@`.
+        let local_part = email.local_part().to_lowercase();
+        let mailbox = local_part.split('+').next().unwrap_or_default();
+        if self.reject_role_accounts && ROLE_ACCOUNTS.contains(&mailbox) {
+            return Err(EmailError::RoleAccount {
+                email: email.original().to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailPolicy;
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_err, assert_ok};
+
+    fn email(email: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(email.into()).unwrap()
+    }
+
+    #[test]
+    fn disposable_and_blocked_domains_are_rejected() {
+        let policy = EmailPolicy::new(true, &["Spam.example".into()], false);
+
+        assert_err!(policy.check(&email("ursula@mailinator.com")));
+        assert_err!(policy.check(&email("ursula@eu.Mailinator.com")));
+        assert_err!(policy.check(&email("ursula@spam.example")));
+        assert_ok!(policy.check(&email("ursula@notmailinator.com")));
+        assert_ok!(EmailPolicy::new(false, &[], false).check(&email("ursula@mailinator.com")));
+    }
+
+    #[test]
+    fn role_accounts_are_rejected_if_the_policy_says_so() {
+        let policy = EmailPolicy::new(false, &[], true);
+
+        assert_err!(policy.check(&email("postmaster@example.com")));
+        assert_err!(policy.check(&email("NoReply+news@example.com")));
+        assert_ok!(policy.check(&email("ursula@example.com")));
+        assert_ok!(EmailPolicy::new(false, &[], false).check(&email("postmaster@example.com")));
+    }
+}
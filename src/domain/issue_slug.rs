@@ -0,0 +1,63 @@
+use uuid::Uuid;
+
+/// The path segment under which a published issue is readable on the web.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    const MAX_TITLE_LENGTH: usize = 60;
+
+    /// Derive a slug from the title, suffixed with the start of the issue id
+    /// so that issues sharing a title do not collide.
+    pub fn new(title: &str, newsletter_issue_id: Uuid) -> Self {
+        let mut slug = String::new();
+        for c in title.chars().flat_map(char::to_lowercase) {
+            if c.is_alphanumeric() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+            if slug.chars().count() >= Self::MAX_TITLE_LENGTH {
+                break;
+            }
+        }
+        let slug = slug.trim_end_matches('-');
+        let slug = if slug.is_empty() { "issue" } else { slug };
+        let id = newsletter_issue_id.to_simple().to_string();
+        Self(format!("{}-{}", slug, &id[..8]))
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+    use uuid::Uuid;
+
+    fn id() -> Uuid {
+        Uuid::parse_str("0b5e3a2c-1111-4222-8333-444455556666").unwrap()
+    }
+
+    #[test]
+    fn titles_are_lowercased_and_hyphenated() {
+        let slug = IssueSlug::new("  Release Notes: v1.2 & more!  ", id());
+        assert_eq!(slug.as_ref(), "release-notes-v1-2-more-0b5e3a2c");
+    }
+
+    #[test]
+    fn titles_without_alphanumerics_fall_back_to_a_generic_slug() {
+        let slug = IssueSlug::new("!!!", id());
+        assert_eq!(slug.as_ref(), "issue-0b5e3a2c");
+    }
+
+    #[test]
+    fn long_titles_are_truncated() {
+        let slug = IssueSlug::new(&"a".repeat(200), id());
+        assert_eq!(slug.as_ref().len(), IssueSlug::MAX_TITLE_LENGTH + 9);
+    }
+}
@@ -0,0 +1,113 @@
+/// Where a newsletter issue is in its lifecycle.
+///
+/// Issues start as drafts, which are the only ones editors can still change.
+/// Publishing either schedules a draft or starts its delivery right away, and
+/// an issue is sent once its delivery queue has been drained. A delivery can
+/// be paused and resumed, or cancelled before it is over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueStatus {
+    Draft,
+    Scheduled,
+    Sending,
+    Paused,
+    Sent,
+    Cancelled,
+}
+
+impl IssueStatus {
+    pub fn parse(status: String) -> Result<Self, String> {
+        match status.as_str() {
+            "draft" => Ok(Self::Draft),
+            "scheduled" => Ok(Self::Scheduled),
+            "sending" => Ok(Self::Sending),
+            "paused" => Ok(Self::Paused),
+            "sent" => Ok(Self::Sent),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{} is not a known issue status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Draft => "draft",
+            IssueStatus::Scheduled => "scheduled",
+            IssueStatus::Sending => "sending",
+            IssueStatus::Paused => "paused",
+            IssueStatus::Sent => "sent",
+            IssueStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_editable(&self) -> bool {
+        *self == IssueStatus::Draft
+    }
+
+    /// Move to `next`, provided the lifecycle allows it.
+    pub fn transition_to(self, next: IssueStatus) -> Result<IssueStatus, String> {
+        use IssueStatus::*;
+        match (self, next) {
+            (Draft, Scheduled)
+            | (Draft, Sending)
+            | (Draft, Cancelled)
+            | (Scheduled, Sending)
+            | (Scheduled, Cancelled)
+            | (Sending, Paused)
+            | (Sending, Cancelled)
+            | (Sending, Sent)
+            | (Paused, Sending)
+            | (Paused, Cancelled) => Ok(next),
+            _ => Err(format!(
+                "A {} issue cannot become {}.",
+                self.as_str(),
+                next.as_str()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueStatus::{self, *};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in [Draft, Scheduled, Sending, Paused, Sent, Cancelled] {
+            assert_ok_eq!(IssueStatus::parse(status.as_str().into()), status);
+        }
+    }
+
+    #[test]
+    fn a_draft_can_be_scheduled_or_sent_right_away() {
+        assert_ok_eq!(Draft.transition_to(Scheduled), Scheduled);
+        assert_ok_eq!(Draft.transition_to(Sending), Sending);
+        assert_ok_eq!(Scheduled.transition_to(Sending), Sending);
+        assert_ok_eq!(Sending.transition_to(Sent), Sent);
+    }
+
+    #[test]
+    fn a_delivery_can_be_paused_resumed_or_cancelled() {
+        assert_ok_eq!(Sending.transition_to(Paused), Paused);
+        assert_ok_eq!(Paused.transition_to(Sending), Sending);
+        assert_ok_eq!(Sending.transition_to(Cancelled), Cancelled);
+        assert_ok_eq!(Paused.transition_to(Cancelled), Cancelled);
+    }
+
+    #[test]
+    fn an_issue_cannot_skip_or_go_back_in_its_lifecycle() {
+        assert_err!(Draft.transition_to(Sent));
+        assert_err!(Scheduled.transition_to(Draft));
+        assert_err!(Paused.transition_to(Sent));
+        assert_err!(Sent.transition_to(Cancelled));
+        assert_err!(Sent.transition_to(Sending));
+        assert_err!(Cancelled.transition_to(Scheduled));
+    }
+
+    #[test]
+    fn only_drafts_are_editable() {
+        assert!(Draft.is_editable());
+        for status in [Scheduled, Sending, Paused, Sent, Cancelled] {
+            assert!(!status.is_editable());
+        }
+    }
+}
@@ -1,7 +1,25 @@
+mod ab_test;
+mod consent;
+mod delivery_cadence;
+mod delivery_status;
+mod email_policy;
+mod issue_slug;
+mod issue_status;
 mod new_subscriber;
+mod segment;
+mod send_at;
 mod subscriber_email;
 mod subscriber_name;
 
-pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use ab_test::{AbTest, VariantResult};
+pub use consent::{ConsentEventType, ConsentMetadata};
+pub use delivery_cadence::DeliveryCadence;
+pub use delivery_status::DeliveryStatus;
+pub use email_policy::EmailPolicy;
+pub use issue_slug::IssueSlug;
+pub use issue_status::IssueStatus;
+pub use new_subscriber::{NewSubscriber, NewSubscriberError};
+pub use segment::{Segment, SegmentParam};
+pub use send_at::SendAt;
+pub use subscriber_email::{EmailError, SubscriberEmail};
+pub use subscriber_name::{NameError, NameNormalization, NameRules, SubscriberName};
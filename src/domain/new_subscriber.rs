@@ -1,6 +1,85 @@
-use super::{SubscriberEmail, SubscriberName};
+use super::{EmailError, NameError, NameRules, SubscriberEmail, SubscriberName};
+use crate::i18n::{Locale, Localize};
 
+#[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
 }
+
+/// Everything wrong with a subscription, field by field, so that it can all
+/// be fixed at once.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct NewSubscriberError {
+    pub name: Option<NameError>,
+    pub email: Option<EmailError>,
+}
+
+impl NewSubscriber {
+    pub fn parse(
+        name: String,
+        email: String,
+        name_rules: &NameRules,
+    ) -> Result<Self, NewSubscriberError> {
+        match (
+            SubscriberName::parse_with_rules(name, name_rules),
+            SubscriberEmail::parse(email),
+        ) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err(NewSubscriberError {
+                name: name.err(),
+                email: email.err(),
+            }),
+        }
+    }
+}
+
+impl Localize for NewSubscriberError {
+    fn localize(&self, locale: Locale) -> String {
+        let messages: Vec<String> = [
+            self.name.as_ref().map(|e| e.localize(locale)),
+            self.email.as_ref().map(|e| e.localize(locale)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        messages.join(" ")
+    }
+}
+
+impl std::fmt::Display for NewSubscriberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(Locale::En))
+    }
+}
+
+impl std::error::Error for NewSubscriberError {}
+
+impl From<EmailError> for NewSubscriberError {
+    fn from(e: EmailError) -> Self {
+        Self {
+            email: Some(e),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewSubscriber, NewSubscriberError};
+    use crate::domain::{EmailError, NameError, NameRules};
+    use claims::assert_err_eq;
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        assert_err_eq!(
+            NewSubscriber::parse("".into(), "not-an-email".into(), &NameRules::default()),
+            NewSubscriberError {
+                name: Some(NameError::Empty),
+                email: Some(EmailError::Invalid {
+                    email: "not-an-email".into()
+                }),
+            }
+        );
+    }
+}
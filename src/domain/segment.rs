@@ -0,0 +1,522 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::domain::DeliveryCadence;
+
+/// A filter on subscribers, e.g.
+/// `subscribed_at > 2023-01-01 AND status = confirmed AND custom.country = 'TW'`.
+///
+/// Conditions combine with `AND`, `OR` and `NOT`, with the usual precedence,
+/// and can be grouped with parentheses. Values are checked against the type
+/// of the field they are compared to. A condition on a custom attribute that
+/// a subscriber does not have is never met.
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+    Not(Box<Segment>),
+    Condition(Field, Operator, Value),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Field {
+    SubscribedAt,
+    Status,
+    Email,
+    Name,
+    DeliveryCadence,
+    Custom(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Timestamp(DateTime<Utc>),
+}
+
+/// A value to bind to the placeholder of the same position in the SQL a
+/// segment compiles to.
+#[derive(Debug, PartialEq)]
+pub enum SegmentParam {
+    Text(String),
+    Number(f64),
+    Json(serde_json::Value),
+    Timestamp(DateTime<Utc>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+impl Segment {
+    const MAX_LENGTH: usize = 2000;
+    const MAX_DEPTH: usize = 32;
+
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        if expression.len() > Self::MAX_LENGTH {
+            return Err(format!(
+                "A segment cannot be longer than {} characters.",
+                Self::MAX_LENGTH
+            ));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+            depth: 0,
+        };
+        let segment = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {} in segment.", describe(token))),
+        }
+    }
+
+    /// Compile the segment into a boolean SQL expression over the
+    /// `subscriptions` table. Values are never spliced into the SQL: they
+    /// are returned as parameters, whose placeholders start at
+    /// `first_placeholder`.
+    pub fn to_sql(&self, first_placeholder: usize) -> (String, Vec<SegmentParam>) {
+        let mut params = Vec::new();
+        let sql = self.compile(first_placeholder, &mut params);
+        (sql, params)
+    }
+
+    fn compile(&self, first_placeholder: usize, params: &mut Vec<SegmentParam>) -> String {
+        let bind = |params: &mut Vec<SegmentParam>, param: SegmentParam| {
+            params.push(param);
+            format!("${}", first_placeholder + params.len() - 1)
+        };
+        match self {
+            Segment::And(left, right) => format!(
+                "({} AND {})",
+                left.compile(first_placeholder, params),
+                right.compile(first_placeholder, params)
+            ),
+            Segment::Or(left, right) => format!(
+                "({} OR {})",
+                left.compile(first_placeholder, params),
+                right.compile(first_placeholder, params)
+            ),
+            Segment::Not(segment) => {
+                format!("(NOT {})", segment.compile(first_placeholder, params))
+            }
+            Segment::Condition(Field::Custom(key), operator, value) => {
+                let key = bind(params, SegmentParam::Text(key.clone()));
+                match value {
+                    Value::Number(number) if !matches!(operator, Operator::Eq | Operator::Ne) => {
+                        let number = bind(params, SegmentParam::Number(*number));
+                        format!(
+                            "(CASE WHEN jsonb_typeof(subscriptions.custom_attributes -> {0}::text) = 'number' \
+THEN (subscriptions.custom_attributes ->> {0}::text)::float8 END {1} {2})",
+                            key,
+                            operator.as_sql(),
+                            number
+                        )
+                    }
+                    value => {
+                        let value = bind(params, SegmentParam::Json(value.to_json()));
+                        format!(
+                            "(subscriptions.custom_attributes -> {}::text {} {}::jsonb)",
+                            key,
+                            operator.as_sql(),
+                            value
+                        )
+                    }
+                }
+            }
+            Segment::Condition(field, operator, value) => {
+                let column = match field {
+                    Field::SubscribedAt => "subscriptions.subscribed_at",
+                    Field::Status => "subscriptions.status",
+                    Field::Email => "subscriptions.email",
+                    Field::Name => "subscriptions.name",
+                    Field::DeliveryCadence => "subscriptions.delivery_cadence",
+                    Field::Custom(_) => unreachable!(),
+                };
+                let value = bind(
+                    params,
+                    match value {
+                        Value::Timestamp(timestamp) => SegmentParam::Timestamp(*timestamp),
+                        Value::Text(text) => SegmentParam::Text(text.clone()),
+                        Value::Number(_) | Value::Bool(_) => {
+                            unreachable!("Built-in fields only hold text and timestamps.")
+                        }
+                    },
+                );
+                format!("({} {} {})", column, operator.as_sql(), value)
+            }
+        }
+    }
+}
+
+impl Operator {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Ne => "<>",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+        }
+    }
+}
+
+impl Value {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Text(text) => text.clone().into(),
+            Value::Number(number) => (*number).into(),
+            Value::Bool(boolean) => (*boolean).into(),
+            Value::Timestamp(timestamp) => timestamp.to_rfc3339().into(),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Segment, String> {
+        let mut segment = self.and()?;
+        while self.next_is_keyword("OR") {
+            segment = Segment::Or(Box::new(segment), Box::new(self.and()?));
+        }
+        Ok(segment)
+    }
+
+    fn and(&mut self) -> Result<Segment, String> {
+        let mut segment = self.not()?;
+        while self.next_is_keyword("AND") {
+            segment = Segment::And(Box::new(segment), Box::new(self.not()?));
+        }
+        Ok(segment)
+    }
+
+    fn not(&mut self) -> Result<Segment, String> {
+        self.depth += 1;
+        if self.depth > Segment::MAX_DEPTH {
+            return Err("The segment is nested too deeply.".into());
+        }
+        let segment = if self.next_is_keyword("NOT") {
+            Segment::Not(Box::new(self.not()?))
+        } else if self.tokens.get(self.position) == Some(&Token::Open) {
+            self.position += 1;
+            let segment = self.or()?;
+            match self.next() {
+                Some(Token::Close) => segment,
+                _ => return Err("A parenthesis of the segment is never closed.".into()),
+            }
+        } else {
+            self.condition()?
+        };
+        self.depth -= 1;
+        Ok(segment)
+    }
+
+    fn condition(&mut self) -> Result<Segment, String> {
+        let field = match self.next() {
+            Some(Token::Word(field)) => parse_field(field)?,
+            Some(token) => return Err(format!("Expected a field, found {}.", describe(token))),
+            None => return Err("The segment ends where a condition was expected.".into()),
+        };
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => *operator,
+            Some(token) => {
+                return Err(format!(
+                    "Expected a comparison operator, found {}.",
+                    describe(token)
+                ))
+            }
+            None => return Err("The segment ends where an operator was expected.".into()),
+        };
+        let value = match self.next() {
+            Some(Token::Word(word)) => parse_bare_value(word),
+            Some(Token::Quoted(text)) => Value::Text(text.clone()),
+            Some(token) => return Err(format!("Expected a value, found {}.", describe(token))),
+            None => return Err("The segment ends where a value was expected.".into()),
+        };
+        let value = check_condition(&field, operator, value)?;
+        Ok(Segment::Condition(field, operator, value))
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' => Token::Operator(Operator::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Ne),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Le),
+            '<' if chars.next_if_eq(&'>').is_some() => Token::Operator(Operator::Ne),
+            '<' => Token::Operator(Operator::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Operator(Operator::Ge),
+            '>' => Token::Operator(Operator::Gt),
+            '\'' | '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // A doubled quote stands for the quote itself.
+                        Some(q) if q == c && chars.next_if_eq(&c).is_some() => text.push(c),
+                        Some(q) if q == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err("A quoted value of the segment is never closed.".into()),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| is_word_char(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            other => return Err(format!("Unexpected character {} in segment.", other)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+' | ':')
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => word.clone(),
+        Token::Quoted(text) => format!("'{}'", text),
+        Token::Operator(operator) => operator.as_sql().into(),
+        Token::Open => "(".into(),
+        Token::Close => ")".into(),
+    }
+}
+
+fn parse_field(field: &str) -> Result<Field, String> {
+    match field {
+        "subscribed_at" => Ok(Field::SubscribedAt),
+        "status" => Ok(Field::Status),
+        "email" => Ok(Field::Email),
+        "name" => Ok(Field::Name),
+        "delivery_cadence" => Ok(Field::DeliveryCadence),
+        field => match field.strip_prefix("custom.") {
+            Some(key)
+                if !key.is_empty()
+                    && key.len() <= 64
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                Ok(Field::Custom(key.into()))
+            }
+            _ => Err(format!(
+                "{} is not a field subscribers can be filtered on.",
+                field
+            )),
+        },
+    }
+}
+
+fn parse_bare_value(word: &str) -> Value {
+    match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        word => match word.parse::<f64>() {
+            Ok(number) if number.is_finite() => Value::Number(number),
+            _ => Value::Text(word.into()),
+        },
+    }
+}
+
+/// Check that the value fits the field and the operator, converting it to
+/// the type of the field.
+fn check_condition(field: &Field, operator: Operator, value: Value) -> Result<Value, String> {
+    let is_equality = matches!(operator, Operator::Eq | Operator::Ne);
+    match (field, value) {
+        (Field::SubscribedAt, value) => {
+            parse_timestamp(&value)
+                .map(Value::Timestamp)
+                .ok_or_else(|| {
+                    "subscribed_at has to be compared to a date, e.g. 2023-01-01.".to_string()
+                })
+        }
+        (_, _) if !is_equality && !matches!(field, Field::Custom(_)) => Err(format!(
+            "{} can only be compared with = or !=.",
+            field_name(field)
+        )),
+        (Field::Status, Value::Text(status))
+            if status == "confirmed" || status == "pending_confirmation" =>
+        {
+            Ok(Value::Text(status))
+        }
+        (Field::Status, _) => Err("status is either `confirmed` or `pending_confirmation`.".into()),
+        (Field::DeliveryCadence, Value::Text(cadence)) => {
+            DeliveryCadence::parse(cadence).map(|cadence| Value::Text(cadence.as_str().into()))
+        }
+        (Field::Email | Field::Name, Value::Text(text)) => Ok(Value::Text(text)),
+        (Field::Email | Field::Name | Field::DeliveryCadence, _) => {
+            Err(format!("{} has to be compared to text.", field_name(field)))
+        }
+        (Field::Custom(_), value @ Value::Number(_)) => Ok(value),
+        (Field::Custom(key), _) if !is_equality => Err(format!(
+            "custom.{} can only be compared with <, <=, > or >= to a number.",
+            key
+        )),
+        (Field::Custom(_), value) => Ok(value),
+    }
+}
+
+fn field_name(field: &Field) -> String {
+    match field {
+        Field::SubscribedAt => "subscribed_at".into(),
+        Field::Status => "status".into(),
+        Field::Email => "email".into(),
+        Field::Name => "name".into(),
+        Field::DeliveryCadence => "delivery_cadence".into(),
+        Field::Custom(key) => format!("custom.{}", key),
+    }
+}
+
+/// Dates stand for midnight UTC; full RFC 3339 timestamps are taken as is.
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let text = match value {
+        Value::Text(text) => text,
+        _ => return None,
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .map(|midnight| Utc.from_utc_datetime(&midnight));
+    }
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, Operator, Segment, SegmentParam, Value};
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn conditions_are_typed_by_their_field() {
+        let segment = assert_ok!(Segment::parse(
+            "subscribed_at > 2023-01-01 AND status = confirmed AND custom.country = 'TW'"
+        ));
+        let expected = Segment::And(
+            Box::new(Segment::And(
+                Box::new(Segment::Condition(
+                    Field::SubscribedAt,
+                    Operator::Gt,
+                    Value::Timestamp(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()),
+                )),
+                Box::new(Segment::Condition(
+                    Field::Status,
+                    Operator::Eq,
+                    Value::Text("confirmed".into()),
+                )),
+            )),
+            Box::new(Segment::Condition(
+                Field::Custom("country".into()),
+                Operator::Eq,
+                Value::Text("TW".into()),
+            )),
+        );
+        assert_eq!(segment, expected);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = assert_ok!(Segment::parse(
+            "custom.a = 1 OR custom.b = 2 AND NOT (custom.c != true)"
+        ));
+        assert!(matches!(
+            segment,
+            Segment::Or(_, right) if matches!(*right, Segment::And(_, ref not) if matches!(**not, Segment::Not(_)))
+        ));
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for expression in [
+            "",
+            "country = 'TW'",
+            "custom.country-code = 'TW'",
+            "status = unsubscribed",
+            "status > confirmed",
+            "subscribed_at > yesterday",
+            "custom.country < 'TW'",
+            "delivery_cadence = monthly",
+            "custom.age > 18 AND",
+            "(custom.age > 18",
+            "custom.age > 18)",
+            "custom.name = 'unterminated",
+            "custom.age ~ 18",
+        ] {
+            assert_err!(Segment::parse(expression), "{} was accepted.", expression);
+        }
+        let deeply_nested = format!("{}custom.a = 1{}", "(".repeat(100), ")".repeat(100));
+        assert_err!(Segment::parse(&deeply_nested));
+    }
+
+    #[test]
+    fn values_are_bound_as_parameters() {
+        let segment = assert_ok!(Segment::parse(
+            "custom.age >= 18 OR name = 'Robert''); DROP TABLE x;--'"
+        ));
+        let (sql, params) = segment.to_sql(2);
+
+        assert_eq!(
+            sql,
+            "((CASE WHEN jsonb_typeof(subscriptions.custom_attributes -> $2::text) = 'number' \
+THEN (subscriptions.custom_attributes ->> $2::text)::float8 END >= $3) OR (subscriptions.name = $4))"
+        );
+        assert_eq!(
+            params,
+            vec![
+                SegmentParam::Text("age".into()),
+                SegmentParam::Number(18.0),
+                SegmentParam::Text("Robert'); DROP TABLE x;--".into()),
+            ]
+        );
+    }
+}
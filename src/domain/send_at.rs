@@ -0,0 +1,106 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// When a scheduled issue goes out. Editors give either an RFC 3339 timestamp
+/// or a local time together with the IANA timezone it is expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendAt {
+    time: DateTime<Utc>,
+    timezone: Tz,
+}
+
+impl SendAt {
+    pub fn parse(send_at: String, timezone: Option<String>) -> Result<Self, String> {
+        let timezone = match timezone {
+            Some(timezone) => timezone
+                .parse::<Tz>()
+                .map_err(|_| format!("{} is not a known timezone.", timezone))?,
+            None => Tz::UTC,
+        };
+        if let Ok(time) = DateTime::parse_from_rfc3339(&send_at) {
+            return Ok(Self {
+                time: time.with_timezone(&Utc),
+                timezone,
+            });
+        }
+        let local = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(&send_at, format).ok())
+            .ok_or_else(|| format!("{} is not a valid send time.", send_at))?;
+        // Times skipped by a daylight saving change do not exist, repeated
+        // ones resolve to their first occurrence.
+        let time = timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .ok_or_else(|| format!("{} does not exist in {}.", send_at, timezone))?;
+        Ok(Self {
+            time: time.with_timezone(&Utc),
+            timezone,
+        })
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    pub fn timezone(&self) -> &'static str {
+        self.timezone.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendAt;
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn local_times_are_converted_from_their_timezone() {
+        let send_at = SendAt::parse("2023-01-10T09:00:00".into(), Some("Asia/Taipei".into()));
+        let send_at = assert_ok!(send_at);
+        assert_eq!(
+            send_at.time(),
+            Utc.with_ymd_and_hms(2023, 1, 10, 1, 0, 0).unwrap()
+        );
+        assert_eq!(send_at.timezone(), "Asia/Taipei");
+    }
+
+    #[test]
+    fn local_times_default_to_utc() {
+        let send_at = assert_ok!(SendAt::parse("2023-01-10T09:00".into(), None));
+        assert_eq!(
+            send_at.time(),
+            Utc.with_ymd_and_hms(2023, 1, 10, 9, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn rfc3339_timestamps_keep_their_offset() {
+        let send_at = assert_ok!(SendAt::parse("2023-01-10T09:00:00+08:00".into(), None));
+        assert_eq!(
+            send_at.time(),
+            Utc.with_ymd_and_hms(2023, 1, 10, 1, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_timezones_are_rejected() {
+        assert_err!(SendAt::parse(
+            "2023-01-10T09:00:00".into(),
+            Some("Mars/Olympus_Mons".into())
+        ));
+    }
+
+    #[test]
+    fn malformed_times_are_rejected() {
+        assert_err!(SendAt::parse("next tuesday".into(), None));
+    }
+
+    #[test]
+    fn times_skipped_by_daylight_saving_are_rejected() {
+        assert_err!(SendAt::parse(
+            "2023-03-12T02:30:00".into(),
+            Some("America/New_York".into())
+        ));
+    }
+}
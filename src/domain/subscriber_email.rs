@@ -1,34 +1,203 @@
+use unicode_normalization::UnicodeNormalization;
 use validator::validate_email;
 
+use crate::i18n::{Locale, Localize};
+
+/// An email address in normalized form: NFC, with its domain lowercased and
+/// internationalized domains in punycode. The address as it was typed is kept
+/// alongside it.
 #[derive(Debug)]
-pub struct SubscriberEmail(String);
+pub struct SubscriberEmail {
+    normalized: String,
+    original: String,
+}
+
+/// Why an email address was not accepted. Clients get the `code` and the
+/// fields next to it, to word the error in their own language.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum EmailError {
+    Empty,
+    Invalid {
+        email: String,
+    },
+    BlockedDomain {
+        domain: String,
+    },
+    RoleAccount {
+        email: String,
+    },
+    /// A likely typo, with the address we think was meant.
+    Mistyped {
+        email: String,
+        suggestion: String,
+    },
+    Undeliverable {
+        domain: String,
+    },
+}
+
+impl Localize for EmailError {
+    fn localize(&self, locale: Locale) -> String {
+        match self {
+            EmailError::Empty => locale.message("email-empty", &[]),
+            EmailError::Invalid { email } => {
+                locale.message("email-invalid", &[("email", email.as_str().into())])
+            }
+            EmailError::BlockedDomain { domain } => locale.message(
+                "email-blocked-domain",
+                &[("domain", domain.as_str().into())],
+            ),
+            EmailError::RoleAccount { email } => {
+                locale.message("email-role-account", &[("email", email.as_str().into())])
+            }
+            EmailError::Mistyped { email, suggestion } => locale.message(
+                "email-mistyped",
+                &[
+                    ("email", email.as_str().into()),
+                    ("suggestion", suggestion.as_str().into()),
+                ],
+            ),
+            EmailError::Undeliverable { domain } => {
+                locale.message("email-undeliverable", &[("domain", domain.as_str().into())])
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(Locale::En))
+    }
+}
+
+impl std::error::Error for EmailError {}
 
 impl SubscriberEmail {
-    pub fn parse(email: String) -> Result<Self, String> {
-        if validate_email(&email) {
-            Ok(Self(email))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", email))
+    pub fn parse(email: String) -> Result<Self, EmailError> {
+        let original = email.trim().to_string();
+        if original.is_empty() {
+            return Err(EmailError::Empty);
+        }
+        match normalize(&original) {
+            Some(normalized) if validate_email(&normalized) => Ok(Self {
+                normalized,
+                original,
+            }),
+            _ => Err(EmailError::Invalid { email }),
         }
     }
+
+    /// The address as the subscriber typed it.
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.split().0
+    }
+
+    /// In its ASCII form.
+    pub fn domain(&self) -> &str {
+        self.split().1
+    }
+
+    fn split(&self) -> (&str, &str) {
+        self.normalized
+            .rsplit_once('@')
+            .expect("A parsed email address has an @.")
+    }
+}
+
+fn normalize(email: &str) -> Option<String> {
+    let email: String = email.nfc().collect();
+    let (local_part, domain) = email.rsplit_once('@')?;
+    if local_part.is_empty() {
+        return None;
+    }
+    let domain = idna::domain_to_ascii(domain).ok()?;
+    Some(format!("{}@{}", local_part, domain))
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.normalized
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use claims::assert_err;
+    use claims::{assert_err, assert_err_eq};
+    use proptest::prelude::*;
+
+    use crate::domain::{EmailError, SubscriberEmail};
+
+    fn local_part() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9][a-zA-Z0-9._%+-]{0,20}"
+    }
+
+    /// Domains of one to three labels under a top level domain. Labels
+    /// starting with `xn--` are punycode, which random ones seldom are.
+    fn domain() -> impl Strategy<Value = String> {
+        (
+            prop::collection::vec("[a-z0-9]([a-z0-9-]{0,10}[a-z0-9])?", 1..=3),
+            "[a-z]{2,6}",
+        )
+            .prop_filter("Invalid punycode label", |(labels, _)| {
+                !labels.iter().any(|label| label.starts_with("xn--"))
+            })
+            .prop_map(|(labels, tld)| format!("{}.{}", labels.join("."), tld))
+    }
+
+    proptest! {
+        #[test]
+        fn valid_emails_are_accepted(local_part in local_part(), domain in domain()) {
+            let email = format!("{}@{}", local_part, domain);
+            let parsed = SubscriberEmail::parse(email.clone()).unwrap();
+            prop_assert_eq!(parsed.as_ref(), email.as_str());
+            prop_assert_eq!(parsed.local_part(), local_part.as_str());
+        }
+
+        #[test]
+        fn normalizing_twice_changes_nothing(local_part in local_part(), domain in domain()) {
+            let email = format!(" {}@{} ", local_part, domain.to_uppercase());
+            let parsed = SubscriberEmail::parse(email).unwrap();
+            let reparsed = SubscriberEmail::parse(parsed.as_ref().to_string()).unwrap();
+            prop_assert_eq!(parsed.as_ref(), reparsed.as_ref());
+            prop_assert_eq!(parsed.domain(), domain.as_str());
+        }
 
-    use crate::domain::SubscriberEmail;
+        #[test]
+        fn internationalized_domains_become_ascii(
+            local_part in local_part(),
+            label in "[a-zäöüéèçñßøåæ]{1,12}",
+            tld in "[a-z]{2,6}",
+        ) {
+            let email = format!("{}@{}.{}", local_part, label, tld);
+            if let Ok(parsed) = SubscriberEmail::parse(email) {
+                prop_assert!(parsed.domain().is_ascii());
+                let suffix = format!(".{}", tld);
+                prop_assert!(parsed.domain().ends_with(&suffix));
+            }
+        }
+
+        #[test]
+        fn strings_without_an_at_are_rejected(email in "[^@]*") {
+            prop_assert!(SubscriberEmail::parse(email).is_err());
+        }
+
+        #[test]
+        fn parsing_anything_never_panics(email in any::<String>()) {
+            if let Ok(parsed) = SubscriberEmail::parse(email) {
+                prop_assert!(parsed.as_ref().contains('@'));
+            }
+        }
+    }
 
     #[test]
     fn empty_string_is_rejected() {
         let email = " ".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_err_eq!(SubscriberEmail::parse(email), EmailError::Empty);
     }
 
     #[test]
@@ -42,4 +211,30 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn the_domain_is_normalized_and_the_original_kept() {
+        let email = SubscriberEmail::parse(" Ursula.Le.Guin@Example.COM ".into()).unwrap();
+
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+        assert_eq!(email.original(), "Ursula.Le.Guin@Example.COM");
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn internationalized_domains_are_stored_in_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".into()).unwrap();
+
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+        assert_eq!(email.original(), "ursula@Bücher.example");
+    }
+
+    #[test]
+    fn addresses_are_normalized_to_nfc() {
+        // "e" followed by a combining acute accent.
+        let decomposed = SubscriberEmail::parse("ursula@cafe\u{301}.example".into()).unwrap();
+        let composed = SubscriberEmail::parse("ursula@caf\u{e9}.example".into()).unwrap();
+
+        assert_eq!(decomposed.as_ref(), composed.as_ref());
+    }
 }
@@ -1,24 +1,140 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::MixedScript;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::i18n::{Locale, Localize};
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
-impl SubscriberName {
-    pub fn parse(name: String) -> Result<Self, String> {
-        let is_empty_or_whitespace = name.trim().is_empty();
-        let is_too_long = name.graphemes(true).count() > 256;
+/// What makes a valid subscriber name.
+#[derive(Debug, Clone)]
+pub struct NameRules {
+    /// Bounds on the length, in graphemes.
+    pub min_length: usize,
+    pub max_length: usize,
+    pub forbidden_characters: Vec<char>,
+    /// Remove control and zero-width characters before checking the name.
+    pub strip_invisible: bool,
+    /// Reject names mixing scripts, such as a Cyrillic `а` among Latin
+    /// letters, which are used to impersonate someone else.
+    pub reject_confusables: bool,
+    pub normalization: NameNormalization,
+}
 
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = name.chars().any(|g| forbidden_characters.contains(&g));
+/// The Unicode normalization form names are stored in.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameNormalization {
+    None,
+    Nfc,
+    Nfkc,
+}
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", name))
+impl Default for NameRules {
+    fn default() -> Self {
+        Self {
+            min_length: 1,
+            max_length: 256,
+            forbidden_characters: vec!['/', '(', ')', '"', '<', '>', '\\', '{', '}'],
+            strip_invisible: true,
+            reject_confusables: false,
+            normalization: NameNormalization::Nfc,
+        }
+    }
+}
+
+/// Why a name was not accepted, serialized like `EmailError`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum NameError {
+    Empty,
+    TooShort { min: usize, actual: usize },
+    TooLong { max: usize, actual: usize },
+    ForbiddenCharacter { character: char },
+    MixedScripts,
+}
+
+impl Localize for NameError {
+    fn localize(&self, locale: Locale) -> String {
+        match self {
+            NameError::Empty => locale.message("name-empty", &[]),
+            NameError::TooShort { min, actual } => locale.message(
+                "name-too-short",
+                &[("min", (*min).into()), ("actual", (*actual).into())],
+            ),
+            NameError::TooLong { max, actual } => locale.message(
+                "name-too-long",
+                &[("max", (*max).into()), ("actual", (*actual).into())],
+            ),
+            NameError::ForbiddenCharacter { character } => locale.message(
+                "name-forbidden-character",
+                &[("character", format!("{:?}", character).into())],
+            ),
+            NameError::MixedScripts => locale.message("name-mixed-scripts", &[]),
+        }
+    }
+}
+
+impl std::fmt::Display for NameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(Locale::En))
+    }
+}
+
+impl std::error::Error for NameError {}
+
+impl SubscriberName {
+    pub fn parse(name: String) -> Result<Self, NameError> {
+        Self::parse_with_rules(name, &NameRules::default())
+    }
+
+    pub fn parse_with_rules(name: String, rules: &NameRules) -> Result<Self, NameError> {
+        let name: String = match rules.normalization {
+            NameNormalization::None => name,
+            NameNormalization::Nfc => name.nfc().collect(),
+            NameNormalization::Nfkc => name.nfkc().collect(),
+        };
+        let name = if rules.strip_invisible {
+            name.chars().filter(|c| !is_invisible(*c)).collect()
         } else {
-            Ok(Self(name))
+            name
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(NameError::Empty);
+        }
+        let length = name.graphemes(true).count();
+        if length < rules.min_length {
+            return Err(NameError::TooShort {
+                min: rules.min_length,
+                actual: length,
+            });
+        }
+        if length > rules.max_length {
+            return Err(NameError::TooLong {
+                max: rules.max_length,
+                actual: length,
+            });
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| rules.forbidden_characters.contains(c))
+        {
+            return Err(NameError::ForbiddenCharacter { character: c });
+        }
+        if rules.reject_confusables && !name.is_single_script() {
+            return Err(NameError::MixedScripts);
         }
+        Ok(Self(name.to_string()))
     }
 }
 
+/// Control characters and those taking up no space.
+fn is_invisible(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{200B}'..='\u{200F}' | '\u{2060}' | '\u{FEFF}')
+}
+
 impl AsRef<str> for SubscriberName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -27,8 +143,67 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use super::{is_invisible, NameError, NameNormalization, NameRules, SubscriberName};
+    use claims::{assert_err, assert_err_eq, assert_ok};
+    use proptest::prelude::*;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    proptest! {
+        #[test]
+        fn names_of_letters_are_accepted(name in "\\p{L}[\\p{L}\\p{M} '.-]{0,60}") {
+            let parsed = SubscriberName::parse(name.clone());
+            prop_assert!(parsed.is_ok(), "{:?} was rejected: {:?}", name, parsed);
+        }
+
+        #[test]
+        fn accepted_names_follow_the_rules(name in any::<String>()) {
+            let rules = NameRules::default();
+            if let Ok(parsed) = SubscriberName::parse_with_rules(name, &rules) {
+                let parsed = parsed.as_ref();
+                prop_assert_eq!(parsed, parsed.trim());
+                prop_assert!(!parsed.is_empty());
+                prop_assert!(parsed.graphemes(true).count() <= rules.max_length);
+                prop_assert!(!parsed.chars().any(|c| is_invisible(c) || rules.forbidden_characters.contains(&c)));
+            }
+        }
+
+        #[test]
+        fn parsing_a_parsed_name_changes_nothing(name in any::<String>()) {
+            if let Ok(parsed) = SubscriberName::parse(name) {
+                let reparsed = SubscriberName::parse(parsed.as_ref().to_string()).unwrap();
+                prop_assert_eq!(parsed.as_ref(), reparsed.as_ref());
+            }
+        }
+
+        #[test]
+        fn names_with_a_forbidden_character_are_rejected(
+            before in "[a-z]{0,10}",
+            forbidden in prop::sample::select(NameRules::default().forbidden_characters),
+            after in "[a-z]{0,10}",
+        ) {
+            let name = format!("{}{}{}", before, forbidden, after);
+            prop_assert_eq!(
+                SubscriberName::parse(name).unwrap_err(),
+                NameError::ForbiddenCharacter { character: forbidden }
+            );
+        }
+
+        #[test]
+        fn names_too_long_are_rejected(name in "\\PC{257,300}") {
+            let rules = NameRules {
+                forbidden_characters: vec![],
+                strip_invisible: false,
+                normalization: NameNormalization::None,
+                ..NameRules::default()
+            };
+            let length = name.trim().graphemes(true).count();
+            prop_assume!(length > 256);
+            prop_assert_eq!(
+                SubscriberName::parse_with_rules(name, &rules).unwrap_err(),
+                NameError::TooLong { max: 256, actual: length }
+            );
+        }
+    }
 
     #[test]
     fn a_256_grapgeme_long_name_is_valid() {
@@ -39,7 +214,13 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_grapgeme_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(
+            SubscriberName::parse(name),
+            NameError::TooLong {
+                max: 256,
+                actual: 257
+            }
+        );
     }
 
     #[test]
@@ -66,4 +247,61 @@ mod tests {
         let name = "marvin hsu".to_string();
         assert_ok!(SubscriberName::parse(name));
     }
+
+    #[test]
+    fn invisible_characters_are_stripped() {
+        let name = SubscriberName::parse("\u{200B}ursula\u{0007} le guin\u{FEFF}".into()).unwrap();
+        assert_eq!(name.as_ref(), "ursula le guin");
+
+        let rules = NameRules {
+            strip_invisible: false,
+            forbidden_characters: vec!['\u{200B}'],
+            ..NameRules::default()
+        };
+        assert_err_eq!(
+            SubscriberName::parse_with_rules("ursula\u{200B}".into(), &rules),
+            NameError::ForbiddenCharacter {
+                character: '\u{200B}'
+            }
+        );
+    }
+
+    #[test]
+    fn names_are_normalized_as_configured() {
+        let decomposed = "rene\u{301}".to_string();
+        assert_eq!(
+            SubscriberName::parse(decomposed).unwrap().as_ref(),
+            "ren\u{e9}"
+        );
+
+        let rules = NameRules {
+            normalization: NameNormalization::Nfkc,
+            ..NameRules::default()
+        };
+        let name = SubscriberName::parse_with_rules("ｕｒｓｕｌａ".into(), &rules).unwrap();
+        assert_eq!(name.as_ref(), "ursula");
+    }
+
+    #[test]
+    fn names_mixing_scripts_are_rejected_if_the_rules_say_so() {
+        let rules = NameRules {
+            min_length: 3,
+            reject_confusables: true,
+            ..NameRules::default()
+        };
+        // The first letter is a Cyrillic `а`.
+        assert_err_eq!(
+            SubscriberName::parse_with_rules("\u{430}dmin".into(), &rules),
+            NameError::MixedScripts
+        );
+        assert_ok!(SubscriberName::parse_with_rules("Владимир".into(), &rules));
+        assert_ok!(SubscriberName::parse_with_rules(
+            "山田 はなこ".into(),
+            &rules
+        ));
+        assert_err_eq!(
+            SubscriberName::parse_with_rules("al".into(), &rules),
+            NameError::TooShort { min: 3, actual: 2 }
+        );
+    }
 }
@@ -21,7 +21,7 @@ impl EmailClient {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
 
         Self {
-            http_client: http_client,
+            http_client,
             base_url,
             sender,
             bear_token,
@@ -44,7 +44,7 @@ impl EmailClient {
                 to: vec![Email {
                     email: recipient.as_ref(),
                 }],
-                subject: subject,
+                subject,
             }],
             content: vec![Content {
                 type_field: content_type,
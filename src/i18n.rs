@@ -0,0 +1,233 @@
+use actix_web::{http::header, HttpRequest};
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use fluent_langneg::{negotiate_languages, NegotiationStrategy};
+use once_cell::sync::Lazy;
+use unic_langid::LanguageIdentifier;
+
+/// A language subscribers can get their emails and pages in. Messages live in
+/// `locales/<locale>/main.ftl`, in the Fluent syntax.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    ZhTw,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::ZhTw];
+
+    /// A locale given explicitly, such as `zh-TW`. Tags naming a variant we
+    /// have no messages for, such as `en-GB`, get the closest one we support.
+    pub fn parse(locale: &str) -> Result<Self, String> {
+        locale
+            .parse::<LanguageIdentifier>()
+            .ok()
+            .and_then(|requested| Self::negotiate(&[requested]))
+            .ok_or_else(|| format!("{} is not a supported locale.", locale))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::ZhTw => "zh-TW",
+        }
+    }
+
+    /// The locale a stored value names, falling back to the default for
+    /// those we no longer support.
+    pub fn from_stored(locale: &str) -> Self {
+        Self::parse(locale).unwrap_or_default()
+    }
+
+    /// The supported locale the client prefers, from its `Accept-Language`
+    /// header.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        req.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Self::from_accept_language)
+            .unwrap_or_default()
+    }
+
+    pub fn from_accept_language(accept_language: &str) -> Self {
+        Self::negotiate(&accepted_languages(accept_language)).unwrap_or_default()
+    }
+
+    /// The first requested language we have messages for.
+    fn negotiate(requested: &[LanguageIdentifier]) -> Option<Self> {
+        let available: Vec<LanguageIdentifier> =
+            Self::ALL.iter().map(Locale::language_identifier).collect();
+        let best = negotiate_languages(requested, &available, None, NegotiationStrategy::Lookup)
+            .into_iter()
+            .next()?;
+        Self::ALL
+            .into_iter()
+            .find(|locale| &locale.language_identifier() == best)
+    }
+
+    fn language_identifier(&self) -> LanguageIdentifier {
+        self.as_str()
+            .parse()
+            .expect("Supported locales are valid language identifiers.")
+    }
+
+    pub fn has_message(&self, id: &str) -> bool {
+        CATALOG[*self as usize].has_message(id)
+    }
+
+    /// The message `id` of the catalog, falling back to English for messages
+    /// not translated yet. Arguments interpolated into HTML have to be escaped
+    /// by the caller.
+    pub fn message(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let args = (!args.is_empty()).then(|| {
+            let mut fluent_args = FluentArgs::new();
+            for (name, value) in args {
+                fluent_args.set(*name, value.clone());
+            }
+            fluent_args
+        });
+        [*self, Locale::default()]
+            .iter()
+            .find_map(|locale| {
+                let bundle = &CATALOG[*locale as usize];
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = vec![];
+                let message = bundle.format_pattern(pattern, args.as_ref(), &mut errors);
+                if !errors.is_empty() {
+                    tracing::error!(?errors, message_id = id, "Failed to format a message.");
+                }
+                Some(message.into_owned())
+            })
+            .unwrap_or_else(|| {
+                tracing::error!(message_id = id, "A message is missing from the catalog.");
+                id.to_string()
+            })
+    }
+}
+
+/// Errors subscribers get to read, worded in their language.
+pub trait Localize {
+    fn localize(&self, locale: Locale) -> String;
+}
+
+/// Messages of each locale, in the order of `Locale::ALL`.
+static CATALOG: Lazy<Vec<FluentBundle<FluentResource>>> = Lazy::new(|| {
+    Locale::ALL
+        .iter()
+        .map(|locale| {
+            let resource = FluentResource::try_new(catalog_source(*locale).to_string())
+                .expect("The message catalog is valid Fluent.");
+            let mut bundle = FluentBundle::new_concurrent(vec![locale.language_identifier()]);
+            // Messages end up in HTML, where the Unicode isolation marks
+            // around arguments would show up as stray characters in some
+            // clients.
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .expect("Message ids are unique in the catalog.");
+            bundle
+        })
+        .collect()
+});
+
+fn catalog_source(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => include_str!("../locales/en/main.ftl"),
+        Locale::ZhTw => include_str!("../locales/zh-TW/main.ftl"),
+    }
+}
+
+/// The languages of an `Accept-Language` header, most wanted first. Those
+/// with a weight of zero are not wanted at all.
+fn accepted_languages(accept_language: &str) -> Vec<LanguageIdentifier> {
+    let mut languages: Vec<(LanguageIdentifier, f32)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let language = parts.next()?.parse().ok()?;
+            let weight = parts
+                .find_map(|part| part.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse().ok())?;
+            Some((language, weight))
+        })
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect();
+    // A stable sort keeps the order of the header between equal weights.
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages
+        .into_iter()
+        .map(|(language, _)| language)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catalog_source, Locale, CATALOG};
+
+    #[test]
+    fn the_preferred_supported_language_is_picked() {
+        assert_eq!(
+            Locale::from_accept_language("zh-TW,zh;q=0.9,en-US;q=0.8"),
+            Locale::ZhTw
+        );
+        assert_eq!(
+            Locale::from_accept_language("en;q=0.5, zh-TW"),
+            Locale::ZhTw
+        );
+        assert_eq!(Locale::from_accept_language("zh-Hant-TW"), Locale::ZhTw);
+        assert_eq!(
+            Locale::from_accept_language("fr-FR, en-GB;q=0.7"),
+            Locale::En
+        );
+        assert_eq!(Locale::from_accept_language("zh-TW;q=0, fr"), Locale::En);
+        assert_eq!(Locale::from_accept_language("*"), Locale::En);
+        assert_eq!(Locale::from_accept_language(""), Locale::En);
+    }
+
+    #[test]
+    fn explicit_locales_have_to_be_supported() {
+        assert_eq!(Locale::parse("zh-tw"), Ok(Locale::ZhTw));
+        assert_eq!(Locale::parse("en-US"), Ok(Locale::En));
+        assert!(Locale::parse("fr").is_err());
+        assert!(Locale::parse("not a locale").is_err());
+        assert_eq!(Locale::from_stored("fr"), Locale::En);
+    }
+
+    #[test]
+    fn every_message_is_translated() {
+        let ids: Vec<&str> = catalog_source(Locale::En)
+            .lines()
+            .filter_map(|line| line.split_once(" ="))
+            .map(|(id, _)| id)
+            .filter(|id| !id.starts_with(['#', ' ']))
+            .collect();
+        assert!(ids.contains(&"confirmation-email-body"));
+        for locale in Locale::ALL {
+            for id in &ids {
+                assert!(
+                    CATALOG[locale as usize].has_message(id),
+                    "{} is missing from the {} catalog.",
+                    id,
+                    locale.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn messages_interpolate_their_arguments() {
+        let message = Locale::ZhTw.message("unsubscribed-message", &[("list", "週報".into())]);
+        assert_eq!(message, "您將不會再收到週報。");
+        assert_eq!(
+            Locale::En.message(
+                "name-too-long",
+                &[("actual", 1000.into()), ("max", 256.into())]
+            ),
+            "The name is 1000 characters long, it can be at most 256."
+        );
+        assert_eq!(
+            Locale::En.message("no-such-message", &[]),
+            "no-such-message"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+use std::time::Duration;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use chrono::Utc;
+
+use crate::{
+    configuration::Settings,
+    domain::{DeliveryStatus, IssueStatus, SubscriberEmail},
+    email_client::EmailClient,
+    i18n::Locale,
+    routes::{generate_subscription_token, get_issue_status},
+    startup::get_connection_pool,
+    tracking::add_tracking,
+};
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), std::io::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration
+        .email_client
+        .client()
+        .map_err(std::io::Error::other)?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), std::io::Error> {
+    loop {
+        match try_execute_task(&pool, &email_client, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_id=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let task = dequeue_task(pool).await?;
+    if task.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let (mut transaction, task) = task.unwrap();
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_id", display(task.subscriber_id));
+
+    let outcome = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let issue = get_issue(pool, task.newsletter_issue_id, &task.locale).await?;
+            let tracking_token = if issue.tracking_enabled {
+                Some(register_recipient(&mut transaction, &task, issue.translated).await?)
+            } else {
+                None
+            };
+            let html_body = issue.html_body(
+                base_url,
+                &task.subscription_token,
+                tracking_token.as_deref(),
+                Locale::from_stored(&task.locale),
+            );
+            // Subscribers taking part in an A/B test get their variant, unless
+            // they get the issue in another language than it was tested in.
+            let subject = match &task.subject {
+                Some(subject) if !issue.translated => subject,
+                _ => &issue.title,
+            };
+            match email_client
+                .send_email(email, subject, "text/html", &html_body)
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber.",
+                    );
+                    // The provider refusing the request itself, rather than
+                    // being unavailable or throttling us, is not worth retrying.
+                    let is_permanent = e
+                        .status()
+                        .map(|s| s.is_client_error() && s != reqwest::StatusCode::TOO_MANY_REQUESTS)
+                        .unwrap_or(false);
+                    Err((e.to_string(), is_permanent))
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            Err((e.to_string(), true))
+        }
+    };
+    // Lock the issue only now, after the task: cancelling locks the issue and
+    // then skips the tasks workers hold, so the locks are never waited for in
+    // opposite orders.
+    let issue_status = get_issue_status(&mut transaction, task.newsletter_issue_id)
+        .await?
+        .expect("A queued task always has an issue.");
+    record_outcome(&mut transaction, &task, outcome, issue_status).await?;
+    mark_issue_as_sent_if_done(&mut transaction, task.newsletter_issue_id).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    subscription_token: String,
+    n_retries: i16,
+    subject: Option<String>,
+    locale: String,
+}
+
+/// Only issues that are being sent are picked up from: pausing or cancelling
+/// an issue stops the workers at their next task, while the tasks they hold
+/// at that moment are still delivered.
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, Task)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+SELECT
+    issue_delivery_queue.newsletter_issue_id,
+    issue_delivery_queue.subscriber_id,
+    issue_delivery_queue.subscription_token,
+    issue_delivery_queue.n_retries,
+    subscriptions.email,
+    subscriptions.locale,
+    subject_variants.subject AS "subject?"
+FROM issue_delivery_queue
+JOIN subscriptions ON subscriptions.id = issue_delivery_queue.subscriber_id
+JOIN newsletter_issues
+    ON newsletter_issues.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+LEFT JOIN subject_variants
+    ON subject_variants.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+    AND subject_variants.variant_index = issue_delivery_queue.variant_index
+WHERE issue_delivery_queue.status IN ('queued', 'retrying')
+    AND issue_delivery_queue.execute_after <= now()
+    AND newsletter_issues.status = 'sending'
+FOR UPDATE OF issue_delivery_queue
+SKIP LOCKED
+LIMIT 1
+"#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    if let Some(r) = r {
+        Ok(Some((
+            transaction,
+            Task {
+                newsletter_issue_id: r.newsletter_issue_id,
+                subscriber_id: r.subscriber_id,
+                subscriber_email: r.email,
+                subscription_token: r.subscription_token,
+                n_retries: r.n_retries,
+                subject: r.subject,
+                locale: r.locale,
+            },
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Keep the task around with the outcome of the attempt. Transient failures
+/// are put back in the queue, backing off exponentially, unless the issue got
+/// cancelled in the meantime.
+#[tracing::instrument(skip_all)]
+async fn record_outcome(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+    outcome: Result<(), (String, bool)>,
+    issue_status: IssueStatus,
+) -> Result<(), sqlx::Error> {
+    let (status, last_error) = match outcome {
+        Ok(()) => (DeliveryStatus::Sent, None),
+        Err((e, is_permanent)) => (
+            DeliveryStatus::after_failure(task.n_retries, is_permanent),
+            Some(e),
+        ),
+    };
+    let status = if status == DeliveryStatus::Retrying && issue_status == IssueStatus::Cancelled {
+        DeliveryStatus::Cancelled
+    } else {
+        status
+    };
+    let (n_retries, execute_after) = if status == DeliveryStatus::Retrying {
+        let backoff = chrono::Duration::seconds(30 * 2i64.pow(task.n_retries as u32));
+        (task.n_retries + 1, Utc::now() + backoff)
+    } else {
+        (task.n_retries, Utc::now())
+    };
+    sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue
+SET status = $3, n_retries = $4, execute_after = $5, last_error = COALESCE($6, last_error), updated_at = $7
+WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+"#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        status.as_str(),
+        n_retries,
+        execute_after,
+        last_error,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Move an issue to sent once no task of its queue is left to deliver. The
+/// issue stays locked until the transaction ends.
+#[tracing::instrument(skip_all)]
+pub async fn mark_issue_as_sent_if_done(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let status = get_issue_status(&mut *transaction, newsletter_issue_id)
+        .await?
+        .map(|status| status.transition_to(IssueStatus::Sent));
+    // Only issues being sent can be done: paused and cancelled ones are not,
+    // whatever is left in their queue.
+    let sent = match status {
+        Some(Ok(sent)) => sent,
+        _ => return Ok(()),
+    };
+    let is_pending = sqlx::query!(
+        r#"
+SELECT EXISTS (
+    SELECT 1 FROM issue_delivery_queue
+    WHERE newsletter_issue_id = $1 AND status IN ('queued', 'retrying', 'held')
+) AS "is_pending!"
+"#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .is_pending;
+    if is_pending {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET status = $2 WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+        sent.as_str()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Give the recipient a random tracking token for this issue, which their
+/// opens and clicks are recorded against. Their clicks go to the links of the
+/// translation they get, if any.
+#[tracing::instrument(skip_all)]
+async fn register_recipient(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+    translated: bool,
+) -> Result<String, sqlx::Error> {
+    // Retries reuse the token handed out on the first attempt.
+    let tracking_token = generate_subscription_token();
+    let locale = if translated { task.locale.as_str() } else { "" };
+    let r = sqlx::query!(
+        r#"
+INSERT INTO issue_recipients (tracking_token, newsletter_issue_id, subscriber_id, sent_at, locale)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+SET sent_at = EXCLUDED.sent_at, locale = EXCLUDED.locale
+RETURNING tracking_token
+"#,
+        tracking_token,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        Utc::now(),
+        locale
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(r.tracking_token)
+}
+
+struct NewsletterIssue {
+    title: String,
+    content: String,
+    slug: Option<String>,
+    tracking_enabled: bool,
+    /// Whether the title and content are those of a variant.
+    translated: bool,
+}
+
+impl NewsletterIssue {
+    fn html_body(
+        &self,
+        base_url: &str,
+        subscription_token: &str,
+        tracking_token: Option<&str>,
+        locale: Locale,
+    ) -> String {
+        let content = match tracking_token {
+            Some(tracking_token) => add_tracking(&self.content, base_url, tracking_token),
+            None => self.content.clone(),
+        };
+        render_issue(
+            &content,
+            base_url,
+            self.slug.as_deref(),
+            subscription_token,
+            locale,
+        )
+    }
+}
+
+/// The body of an issue as a subscriber receives it, footer links included.
+/// Issues that are not published yet have no slug, hence no web version.
+pub fn render_issue(
+    content: &str,
+    base_url: &str,
+    slug: Option<&str>,
+    subscription_token: &str,
+    locale: Locale,
+) -> String {
+    let web_version = match slug {
+        Some(slug) => format!(
+            "<a href=\"{}/newsletters/{}\">{}</a><br />",
+            base_url,
+            slug,
+            locale.message("issue-view-in-browser", &[])
+        ),
+        None => String::new(),
+    };
+    let preferences_link = format!(
+        "{}/subscriptions/preferences?subscription_token={}",
+        base_url, subscription_token
+    );
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        base_url, subscription_token
+    );
+    format!(
+        "{}{}<br />\
+        <a href=\"{}\">{}</a> \
+        <a href=\"{}\">{}</a>",
+        web_version,
+        content,
+        preferences_link,
+        locale.message("issue-manage-preferences", &[]),
+        unsubscribe_link,
+        locale.message("issue-unsubscribe", &[])
+    )
+}
+
+/// The issue in the given locale, if it has a variant for it.
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    locale: &str,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+SELECT
+    COALESCE(newsletter_issue_variants.title, newsletter_issues.title) AS "title!",
+    COALESCE(newsletter_issue_variants.content, newsletter_issues.content) AS "content!",
+    newsletter_issues.slug,
+    newsletter_issues.tracking_enabled,
+    newsletter_issue_variants.locale IS NOT NULL AS "translated!"
+FROM newsletter_issues
+LEFT JOIN newsletter_issue_variants
+    ON newsletter_issue_variants.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+    AND newsletter_issue_variants.locale = $2
+WHERE newsletter_issues.newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        locale
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
@@ -1,6 +1,17 @@
+pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod deliverability;
+pub mod digest_delivery;
 pub mod domain;
 pub mod email_client;
+pub mod i18n;
+pub mod issue_delivery_worker;
+pub mod newsletter_scheduler;
+pub mod onboarding;
+pub mod rate_limiting;
 pub mod routes;
 pub mod startup;
 pub mod telemetry;
+pub mod tracking;
+pub mod utils;
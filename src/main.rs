@@ -1,14 +1,79 @@
-use zero2prod_practice::configuration::get_configuration;
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
+use zero2prod_practice::configuration::{get_configuration, Settings};
+use zero2prod_practice::digest_delivery::run_digests_until_stopped;
+use zero2prod_practice::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod_practice::newsletter_scheduler::run_scheduler_until_stopped;
+use zero2prod_practice::onboarding::run_onboarding_until_stopped;
 use zero2prod_practice::startup::Application;
 use zero2prod_practice::telemetry::{get_subscriber, init_subscriber};
 
+/// With `--check-config`, the configuration is validated and printed, with
+/// secrets redacted, instead of starting the application.
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
+    let check_config = std::env::args().skip(1).any(|arg| arg == "--check-config");
+    let configuration = match load_configuration() {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if check_config {
+        let printed = serde_json::to_string_pretty(&configuration)
+            .expect("The configuration can always be serialized.");
+        println!("{}", printed);
+        return Ok(());
+    }
+
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to read configuration");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    let application = Application::build(configuration.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(configuration.clone()));
+    let digest_task = tokio::spawn(run_digests_until_stopped(configuration.clone()));
+    let onboarding_task = tokio::spawn(run_onboarding_until_stopped(configuration));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = scheduler_task => report_exit("Newsletter scheduler", o),
+        o = digest_task => report_exit("Digest sender", o),
+        o = onboarding_task => report_exit("Onboarding sender", o),
+    };
     Ok(())
 }
+
+fn load_configuration() -> Result<Settings, String> {
+    let configuration =
+        get_configuration().map_err(|e| format!("Failed to read the configuration: {}", e))?;
+    configuration.validate().map_err(|e| e.to_string())?;
+    Ok(configuration)
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
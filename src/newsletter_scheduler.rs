@@ -0,0 +1,390 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{
+        AbTest, DeliveryCadence, DeliveryStatus, IssueSlug, IssueStatus, Segment, SegmentParam,
+        VariantResult,
+    },
+    issue_delivery_worker::mark_issue_as_sent_if_done,
+    startup::get_connection_pool,
+    tracking::tracked_links,
+};
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), std::io::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), std::io::Error> {
+    loop {
+        if let Err(e) = release_due_issues(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to release scheduled newsletter issues.",
+            );
+        }
+        if let Err(e) = decide_due_ab_tests(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to decide A/B tests.",
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+/// Start the delivery of every scheduled issue whose send time has come.
+/// Returns how many issues were released.
+#[tracing::instrument(skip_all, err)]
+pub async fn release_due_issues(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let due_issues = sqlx::query!(
+        r#"
+SELECT newsletter_issue_id
+FROM newsletter_issues
+WHERE status = 'scheduled' AND send_at <= $1
+FOR UPDATE
+SKIP LOCKED
+"#,
+        Utc::now()
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for issue in &due_issues {
+        start_delivery(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    // Workers finishing the last tasks of an issue at the same time can both
+    // miss that the queue ran empty, so we sweep up behind them. Issues a
+    // worker has locked are left to it.
+    let drained_issues = sqlx::query!(
+        r#"
+SELECT newsletter_issue_id
+FROM newsletter_issues
+WHERE status = 'sending'
+    AND NOT EXISTS (
+        SELECT 1 FROM issue_delivery_queue
+        WHERE issue_delivery_queue.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+            AND issue_delivery_queue.status IN ('queued', 'retrying', 'held')
+    )
+FOR UPDATE
+SKIP LOCKED
+"#
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for issue in drained_issues {
+        mark_issue_as_sent_if_done(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(due_issues.len())
+}
+
+/// Queue the issue for every confirmed member of its lists, narrowed down to
+/// its segment if it has one. Subscribers on a digest cadence get it later,
+/// with their next digest. Subscribers on several of the lists get a
+/// single task, carrying the token of one of their memberships so that they
+/// can unsubscribe from it. This is also when the issue gets published to
+/// the web archive.
+#[tracing::instrument(skip(transaction))]
+pub async fn start_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<IssueStatus, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+SELECT title, content, tracking_enabled, segment
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let mut enqueued = sqlx::query!(
+        r#"
+INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id, subscription_token)
+SELECT DISTINCT ON (list_memberships.subscriber_id)
+    $1, list_memberships.subscriber_id, subscription_tokens.subscription_token
+FROM newsletter_issue_lists
+JOIN list_memberships ON list_memberships.list_id = newsletter_issue_lists.list_id
+JOIN subscription_tokens ON subscription_tokens.subscriber_id = list_memberships.subscriber_id
+    AND subscription_tokens.list_id = list_memberships.list_id
+WHERE newsletter_issue_lists.newsletter_issue_id = $1
+    AND list_memberships.status = 'confirmed'
+ORDER BY list_memberships.subscriber_id
+"#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if let Some(segment) = &issue.segment {
+        enqueued -=
+            dequeue_outside_segment(&mut *transaction, newsletter_issue_id, segment).await?;
+    }
+    let digested = sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue SET status = $2
+FROM subscriptions
+WHERE issue_delivery_queue.newsletter_issue_id = $1
+    AND subscriptions.id = issue_delivery_queue.subscriber_id
+    AND subscriptions.delivery_cadence <> $3
+"#,
+        newsletter_issue_id,
+        DeliveryStatus::Digest.as_str(),
+        DeliveryCadence::Immediate.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    let immediate = enqueued - digested;
+    if immediate > 0 {
+        split_ab_test_sample(&mut *transaction, newsletter_issue_id, immediate as i64).await?;
+    }
+    // Without recipients there is nothing left for the workers to drain.
+    let status = if immediate == 0 {
+        IssueStatus::Sent
+    } else {
+        IssueStatus::Sending
+    };
+    if issue.tracking_enabled {
+        store_tracked_links(&mut *transaction, newsletter_issue_id, &issue.content).await?;
+    }
+    let slug = IssueSlug::new(&issue.title, newsletter_issue_id);
+    sqlx::query!(
+        r#"
+UPDATE newsletter_issues SET status = $2, published_at = $3, slug = $4
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        status.as_str(),
+        Utc::now(),
+        slug.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(status)
+}
+
+/// Take the subscribers that do not match the segment of the issue back out
+/// of its queue. Returns how many were taken out.
+#[tracing::instrument(skip(transaction))]
+async fn dequeue_outside_segment(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    segment: &str,
+) -> Result<u64, sqlx::Error> {
+    let segment = Segment::parse(segment).expect("A stored segment is always valid.");
+    let (condition, params) = segment.to_sql(2);
+    // The condition only refers to placeholders, values are bound below.
+    let sql = format!(
+        r#"
+DELETE FROM issue_delivery_queue
+USING subscriptions
+WHERE issue_delivery_queue.newsletter_issue_id = $1
+    AND subscriptions.id = issue_delivery_queue.subscriber_id
+    AND NOT COALESCE({}, false)
+"#,
+        condition
+    );
+    let mut query = sqlx::query(&sql).bind(newsletter_issue_id);
+    for param in params {
+        query = match param {
+            SegmentParam::Text(text) => query.bind(text),
+            SegmentParam::Number(number) => query.bind(number),
+            SegmentParam::Json(value) => query.bind(value),
+            SegmentParam::Timestamp(timestamp) => query.bind(timestamp),
+        };
+    }
+    Ok(query.execute(transaction).await?.rows_affected())
+}
+
+/// If the issue has an A/B test, spread a random sample of its audience over
+/// the subject line variants and hold back the rest until a winner is known.
+#[tracing::instrument(skip(transaction))]
+async fn split_ab_test_sample(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    audience: i64,
+) -> Result<(), sqlx::Error> {
+    let ab_test = match sqlx::query!(
+        r#"
+SELECT
+    sample_percent,
+    wait_minutes,
+    (SELECT COUNT(*) FROM subject_variants WHERE newsletter_issue_id = $1) AS "n_variants!"
+FROM ab_tests
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    {
+        Some(ab_test) => ab_test,
+        None => return Ok(()),
+    };
+    let sample_size = AbTest::sample_size(ab_test.sample_percent, ab_test.n_variants, audience);
+    sqlx::query!(
+        r#"
+WITH sample AS (
+    SELECT subscriber_id, row_number() OVER (ORDER BY random()) - 1 AS position
+    FROM issue_delivery_queue
+    WHERE newsletter_issue_id = $1 AND status = 'queued'
+)
+UPDATE issue_delivery_queue
+SET
+    variant_index = CASE WHEN sample.position < $2 THEN (sample.position % $3)::smallint END,
+    status = CASE WHEN sample.position < $2 THEN 'queued' ELSE $4 END
+FROM sample
+WHERE issue_delivery_queue.newsletter_issue_id = $1
+    AND issue_delivery_queue.subscriber_id = sample.subscriber_id
+"#,
+        newsletter_issue_id,
+        sample_size,
+        ab_test.n_variants,
+        DeliveryStatus::Held.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+UPDATE ab_tests SET decide_at = $2
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        Utc::now() + chrono::Duration::minutes(ab_test.wait_minutes as i64)
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Pick the subject line with the best open rate for every A/B test whose
+/// waiting time is over, and send it to the subscribers held back so far.
+/// Returns how many tests were decided.
+#[tracing::instrument(skip_all, err)]
+pub async fn decide_due_ab_tests(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let due_tests = sqlx::query!(
+        r#"
+SELECT newsletter_issue_id
+FROM ab_tests
+WHERE winning_variant IS NULL AND decide_at <= $1
+FOR UPDATE
+SKIP LOCKED
+"#,
+        Utc::now()
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for test in &due_tests {
+        let results = get_variant_results(&mut transaction, test.newsletter_issue_id).await?;
+        let winner = AbTest::pick_winner(&results).unwrap_or(0);
+        sqlx::query!(
+            r#"
+UPDATE ab_tests SET winning_variant = $2
+WHERE newsletter_issue_id = $1
+"#,
+            test.newsletter_issue_id,
+            winner
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+UPDATE issue_delivery_queue SET status = $3, variant_index = $2, updated_at = $4
+WHERE newsletter_issue_id = $1 AND status = $5
+"#,
+            test.newsletter_issue_id,
+            winner,
+            DeliveryStatus::Queued.as_str(),
+            Utc::now(),
+            DeliveryStatus::Held.as_str()
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(due_tests.len())
+}
+
+/// How each subject line of an A/B test did with its part of the sample.
+/// Only recipients the issue was actually delivered to count.
+#[tracing::instrument(skip(transaction))]
+pub async fn get_variant_results(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<VariantResult>, sqlx::Error> {
+    sqlx::query_as!(
+        VariantResult,
+        r#"
+SELECT
+    subject_variants.variant_index,
+    COUNT(DISTINCT issue_delivery_queue.subscriber_id) AS "sent!",
+    COUNT(DISTINCT engagement_events.tracking_token) AS "opens!"
+FROM subject_variants
+LEFT JOIN issue_delivery_queue
+    ON issue_delivery_queue.newsletter_issue_id = subject_variants.newsletter_issue_id
+    AND issue_delivery_queue.variant_index = subject_variants.variant_index
+    AND issue_delivery_queue.status = 'sent'
+LEFT JOIN issue_recipients
+    ON issue_recipients.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+    AND issue_recipients.subscriber_id = issue_delivery_queue.subscriber_id
+LEFT JOIN engagement_events
+    ON engagement_events.tracking_token = issue_recipients.tracking_token
+WHERE subject_variants.newsletter_issue_id = $1
+GROUP BY subject_variants.variant_index
+ORDER BY subject_variants.variant_index
+"#,
+        newsletter_issue_id
+    )
+    .fetch_all(transaction)
+    .await
+}
+
+/// Remember where the tracked links of an issue and of each of its
+/// translations point to, so that clicks can be redirected. The links of the
+/// issue as written have an empty locale.
+#[tracing::instrument(skip(transaction, content))]
+async fn store_tracked_links(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    content: &str,
+) -> Result<(), sqlx::Error> {
+    let variants = sqlx::query!(
+        r#"SELECT locale, content FROM newsletter_issue_variants WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let contents = std::iter::once((String::new(), content)).chain(
+        variants
+            .iter()
+            .map(|v| (v.locale.clone(), v.content.as_str())),
+    );
+    for (locale, content) in contents {
+        let urls = tracked_links(content);
+        let link_indexes: Vec<i32> = (0..urls.len() as i32).collect();
+        sqlx::query!(
+            r#"
+INSERT INTO issue_links (newsletter_issue_id, locale, link_index, url)
+SELECT $1, $2, link_index, url FROM UNNEST($3::int[], $4::text[]) AS links(link_index, url)
+"#,
+            newsletter_issue_id,
+            locale,
+            &link_indexes,
+            &urls
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
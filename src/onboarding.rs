@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient, i18n::Locale,
+    issue_delivery_worker::render_issue, startup::get_connection_pool, utils::html_escape,
+};
+
+/// The emails new subscribers get once they confirmed, starting with the
+/// welcome email.
+#[derive(Clone, Debug)]
+pub struct OnboardingSequence {
+    steps: Vec<OnboardingStep>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OnboardingStep {
+    pub template: String,
+    /// Counted from the confirmation of the subscriber.
+    pub delay: chrono::Duration,
+}
+
+impl OnboardingSequence {
+    /// Steps are sent in order, so their delays cannot decrease. Their
+    /// templates have to be in the catalog, translations fall back to English.
+    pub fn new(steps: Vec<OnboardingStep>) -> Result<Self, String> {
+        let mut previous_delay = chrono::Duration::zero();
+        for step in &steps {
+            if step.delay < previous_delay {
+                return Err(format!(
+                    "The onboarding step {} is sent before the one preceding it.",
+                    step.template
+                ));
+            }
+            previous_delay = step.delay;
+            for part in ["subject", "body"] {
+                let id = format!("onboarding-{}-{}", step.template, part);
+                if !Locale::default().has_message(&id) {
+                    return Err(format!("{} is missing from the message catalog.", id));
+                }
+            }
+        }
+        Ok(Self { steps })
+    }
+
+    pub fn step(&self, index: usize) -> Option<&OnboardingStep> {
+        self.steps.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+pub async fn run_onboarding_until_stopped(configuration: Settings) -> Result<(), std::io::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration
+        .email_client
+        .client()
+        .map_err(std::io::Error::other)?;
+    let sequence = configuration
+        .onboarding
+        .sequence()
+        .map_err(std::io::Error::other)?;
+    onboarding_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        sequence,
+    )
+    .await
+}
+
+async fn onboarding_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    sequence: OnboardingSequence,
+) -> Result<(), std::io::Error> {
+    loop {
+        if let Err(e) =
+            send_due_onboarding_emails(&pool, &email_client, &base_url, &sequence, Utc::now()).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send onboarding emails.",
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Start the onboarding sequence of a subscriber who just confirmed. Those
+/// confirming another list are already on their way through it.
+#[tracing::instrument(skip(transaction))]
+pub async fn start_onboarding(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO onboarding_progress (subscriber_id, started_at, next_step, status, updated_at)
+VALUES ($1, $2, 0, 'active', $2)
+ON CONFLICT (subscriber_id) DO NOTHING
+"#,
+        subscriber_id,
+        now
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Send the next step of the sequence to every subscriber whose delay is
+/// over. Subscribers who left every list get no more of it. Returns how many
+/// emails were sent.
+#[tracing::instrument(skip(pool, email_client, base_url, sequence), err)]
+pub async fn send_due_onboarding_emails(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    sequence: &OnboardingSequence,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    sqlx::query!(
+        r#"
+UPDATE onboarding_progress SET status = 'stopped', updated_at = $1
+WHERE status = 'active'
+    AND NOT EXISTS (
+        SELECT 1 FROM list_memberships
+        WHERE list_memberships.subscriber_id = onboarding_progress.subscriber_id
+            AND list_memberships.status = 'confirmed'
+    )
+"#,
+        now
+    )
+    .execute(pool)
+    .await?;
+    let candidates = sqlx::query!(
+        r#"
+SELECT subscriber_id, started_at, next_step
+FROM onboarding_progress
+WHERE status = 'active'
+"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut n_sent = 0;
+    for candidate in candidates {
+        let is_due = sequence
+            .step(candidate.next_step as usize)
+            .is_none_or(|step| candidate.started_at + step.delay <= now);
+        if !is_due {
+            continue;
+        }
+        if send_onboarding_email(
+            pool,
+            email_client,
+            base_url,
+            sequence,
+            candidate.subscriber_id,
+            candidate.next_step,
+            now,
+        )
+        .await?
+        {
+            n_sent += 1;
+        }
+    }
+    Ok(n_sent)
+}
+
+/// If the email cannot be sent, the step is tried again on the next run.
+#[tracing::instrument(skip(pool, email_client, base_url, sequence))]
+async fn send_onboarding_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    sequence: &OnboardingSequence,
+    subscriber_id: Uuid,
+    step_index: i32,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let recipient = sqlx::query!(
+        r#"
+SELECT
+    subscriptions.email,
+    subscriptions.name,
+    subscriptions.locale,
+    (
+        SELECT subscription_tokens.subscription_token
+        FROM subscription_tokens
+        JOIN list_memberships
+            ON list_memberships.subscriber_id = subscription_tokens.subscriber_id
+            AND list_memberships.list_id = subscription_tokens.list_id
+        WHERE subscription_tokens.subscriber_id = subscriptions.id
+            AND list_memberships.status = 'confirmed'
+        ORDER BY subscription_tokens.created_at DESC
+        LIMIT 1
+    ) AS "subscription_token!"
+FROM onboarding_progress
+JOIN subscriptions ON subscriptions.id = onboarding_progress.subscriber_id
+WHERE onboarding_progress.subscriber_id = $1
+    AND onboarding_progress.status = 'active'
+    AND onboarding_progress.next_step = $2
+FOR UPDATE OF onboarding_progress
+SKIP LOCKED
+"#,
+        subscriber_id,
+        step_index
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    // Another run got to this subscriber first.
+    let recipient = match recipient {
+        Some(recipient) => recipient,
+        None => return Ok(false),
+    };
+    // The sequence got shorter since they started it.
+    let step = match sequence.step(step_index as usize) {
+        Some(step) => step,
+        None => {
+            set_progress(
+                &mut transaction,
+                subscriber_id,
+                step_index,
+                "completed",
+                now,
+            )
+            .await?;
+            transaction.commit().await?;
+            return Ok(false);
+        }
+    };
+
+    let email = match SubscriberEmail::parse(recipient.email) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Stopping the onboarding of a subscriber. Their stored contact details are invalid",
+            );
+            set_progress(&mut transaction, subscriber_id, step_index, "stopped", now).await?;
+            transaction.commit().await?;
+            return Ok(false);
+        }
+    };
+    let locale = Locale::from_stored(&recipient.locale);
+    let subject = locale.message(&format!("onboarding-{}-subject", step.template), &[]);
+    let content = locale.message(
+        &format!("onboarding-{}-body", step.template),
+        &[
+            ("name", html_escape(&recipient.name).into()),
+            ("base_url", base_url.into()),
+        ],
+    );
+    let html_body = render_issue(
+        &content,
+        base_url,
+        None,
+        &recipient.subscription_token,
+        locale,
+    );
+    if let Err(e) = email_client
+        .send_email(email, &subject, "text/html", &html_body)
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to deliver an onboarding email.",
+        );
+        return Ok(false);
+    }
+
+    let next_step = step_index + 1;
+    let status = if next_step as usize >= sequence.len() {
+        "completed"
+    } else {
+        "active"
+    };
+    set_progress(&mut transaction, subscriber_id, next_step, status, now).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+async fn set_progress(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next_step: i32,
+    status: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+UPDATE onboarding_progress SET next_step = $2, status = $3, updated_at = $4
+WHERE subscriber_id = $1
+"#,
+        subscriber_id,
+        next_step,
+        status,
+        now
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OnboardingSequence, OnboardingStep};
+    use chrono::Duration;
+
+    fn step(template: &str, delay_hours: i64) -> OnboardingStep {
+        OnboardingStep {
+            template: template.into(),
+            delay: Duration::hours(delay_hours),
+        }
+    }
+
+    #[test]
+    fn steps_with_templates_in_the_catalog_make_a_sequence() {
+        let sequence =
+            OnboardingSequence::new(vec![step("welcome", 0), step("archive", 72)]).unwrap();
+        assert_eq!(sequence.len(), 2);
+        assert_eq!(sequence.step(1), Some(&step("archive", 72)));
+        assert_eq!(sequence.step(2), None);
+        assert!(OnboardingSequence::new(vec![]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn steps_cannot_be_sent_before_the_previous_one() {
+        assert!(OnboardingSequence::new(vec![step("welcome", 24), step("archive", 0)]).is_err());
+        assert!(OnboardingSequence::new(vec![step("welcome", -1)]).is_err());
+    }
+
+    #[test]
+    fn templates_have_to_be_in_the_catalog() {
+        let error = OnboardingSequence::new(vec![step("no-such-template", 0)]).unwrap_err();
+        assert_eq!(
+            error,
+            "onboarding-no-such-template-subject is missing from the message catalog."
+        );
+    }
+}
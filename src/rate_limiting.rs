@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures_util::future::BoxFuture;
+use sqlx::PgPool;
+
+use crate::configuration::{
+    RateLimitAlgorithm, RateLimitRule, RateLimitSettings, RateLimitStoreKind,
+};
+
+/// Counts requests per client and route, shared with the middleware as app
+/// data.
+pub struct RateLimiter {
+    rules: Vec<RateLimitRule>,
+    forwarded_for_hops: usize,
+    store: Box<dyn RateLimitStore>,
+}
+
+/// The address of the client, as seen by the proxy closest to it that we
+/// trust. Handlers find it in the request extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+/// Where request counters are kept: in memory for a single instance, in
+/// Postgres when several instances share the limits.
+pub trait RateLimitStore: Send + Sync {
+    /// Count a request in the window starting at `window_start`, returning
+    /// how many requests the window has seen so far.
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<i64, sqlx::Error>>;
+
+    /// Count a request arriving at `now` against a GCRA limit, returning how
+    /// long the client has to wait if it went over. Rejected requests are
+    /// not counted.
+    fn arrive<'a>(
+        &'a self,
+        key: &'a str,
+        now: DateTime<Utc>,
+        emission_interval: Duration,
+        window: Duration,
+    ) -> BoxFuture<'a, Result<Option<Duration>, sqlx::Error>>;
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings, db_pool: PgPool) -> Self {
+        let store: Box<dyn RateLimitStore> = match settings.store {
+            RateLimitStoreKind::Memory => Box::new(InMemoryStore::default()),
+            RateLimitStoreKind::Postgres => Box::new(PostgresStore::new(db_pool)),
+        };
+        Self {
+            rules: settings.rules,
+            forwarded_for_hops: settings.forwarded_for_hops,
+            store,
+        }
+    }
+
+    /// Returns how many seconds the client has to wait if it went over the
+    /// limit of the route.
+    async fn check(
+        &self,
+        req: &HttpRequest,
+        client_ip: Option<IpAddr>,
+        now: DateTime<Utc>,
+    ) -> Option<i64> {
+        let client_ip = client_ip?;
+        let pattern = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let (index, rule) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(req.method().as_str(), &pattern))?;
+        let key = format!("{}:{}", index, client_ip);
+        let window = Duration::seconds(rule.window_seconds);
+        let wait = match rule.algorithm {
+            RateLimitAlgorithm::FixedWindow => {
+                let window_start = window_start(now, rule.window_seconds);
+                let expires_at = window_start + window;
+                self.store
+                    .hit(&key, window_start, expires_at)
+                    .await
+                    .map(|hits| (hits > rule.limit).then(|| expires_at - now))
+            }
+            RateLimitAlgorithm::Gcra => {
+                let emission_interval =
+                    Duration::milliseconds(rule.window_seconds * 1000 / rule.limit);
+                self.store
+                    .arrive(&key, now, emission_interval, window)
+                    .await
+            }
+        };
+        match wait {
+            // Rounded up, so that clients do not come back a bit too early.
+            Ok(wait) => wait.map(|wait| ((wait.num_milliseconds() + 999) / 1000).max(1)),
+            // Better to let requests through than to take the site down with
+            // the store.
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to count a request against its rate limit.",
+                );
+                None
+            }
+        }
+    }
+}
+
+impl RateLimitRule {
+    fn matches(&self, method: &str, pattern: &str) -> bool {
+        self.path == pattern
+            && self
+                .method
+                .as_deref()
+                .is_none_or(|m| m.eq_ignore_ascii_case(method))
+    }
+}
+
+/// Middleware rejecting requests over the limit of their route with a 429.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let rate_limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .expect("The rate limiter is registered as app data.")
+        .clone();
+    let client_ip = client_ip(req.request(), rate_limiter.forwarded_for_hops);
+    if let Some(client_ip) = client_ip {
+        req.extensions_mut().insert(ClientIp(client_ip));
+    }
+    if let Some(retry_after) = rate_limiter
+        .check(req.request(), client_ip, Utc::now())
+        .await
+    {
+        tracing::warn!(client_ip = ?client_ip, "A client went over its rate limit.");
+        let response = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .body("Too many requests, try again later.");
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// The address of the client. Behind `forwarded_for_hops` proxies, each
+/// appending the address it got the request from to `X-Forwarded-For`, it is
+/// the entry that many places from the end: those before it can be forged.
+pub fn client_ip(req: &HttpRequest, forwarded_for_hops: usize) -> Option<IpAddr> {
+    let peer_ip = req.peer_addr().map(|addr| addr.ip());
+    if forwarded_for_hops == 0 {
+        return peer_ip;
+    }
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    forwarded_for
+        .len()
+        .checked_sub(forwarded_for_hops)
+        .and_then(|i| forwarded_for[i].parse().ok())
+        .or(peer_ip)
+}
+
+/// A GCRA limit lets a request through when it arrives no earlier than
+/// `window` before its theoretical arrival time, which each request pushes
+/// `emission_interval` further. Returns the new theoretical arrival time, or
+/// how long the client has to wait.
+fn gcra(
+    theoretical_arrival: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    emission_interval: Duration,
+    window: Duration,
+) -> Result<DateTime<Utc>, Duration> {
+    let theoretical_arrival =
+        theoretical_arrival.map_or(now, |arrival| arrival.max(now)) + emission_interval;
+    let allowed_at = theoretical_arrival - window;
+    if allowed_at > now {
+        Err(allowed_at - now)
+    } else {
+        Ok(theoretical_arrival)
+    }
+}
+
+fn window_start(now: DateTime<Utc>, window_seconds: i64) -> DateTime<Utc> {
+    let timestamp = now.timestamp();
+    Utc.timestamp_opt(timestamp - timestamp.rem_euclid(window_seconds), 0)
+        .single()
+        .expect("The start of a window is a valid timestamp.")
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    counters: Mutex<HashMap<String, Counter>>,
+    arrivals: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+struct Counter {
+    window_start: DateTime<Utc>,
+    hits: i64,
+    expires_at: DateTime<Utc>,
+}
+
+/// Past this many counters, expired ones get dropped.
+const MAX_IN_MEMORY_COUNTERS: usize = 10_000;
+
+impl RateLimitStore for InMemoryStore {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<i64, sqlx::Error>> {
+        let mut counters = self.counters.lock().unwrap();
+        if counters.len() > MAX_IN_MEMORY_COUNTERS {
+            counters.retain(|_, counter| counter.expires_at > window_start);
+        }
+        let counter = counters.entry(key.to_string()).or_insert(Counter {
+            window_start,
+            hits: 0,
+            expires_at,
+        });
+        if counter.window_start != window_start {
+            *counter = Counter {
+                window_start,
+                hits: 0,
+                expires_at,
+            };
+        }
+        counter.hits += 1;
+        let hits = counter.hits;
+        Box::pin(async move { Ok(hits) })
+    }
+
+    fn arrive<'a>(
+        &'a self,
+        key: &'a str,
+        now: DateTime<Utc>,
+        emission_interval: Duration,
+        window: Duration,
+    ) -> BoxFuture<'a, Result<Option<Duration>, sqlx::Error>> {
+        let mut arrivals = self.arrivals.lock().unwrap();
+        if arrivals.len() > MAX_IN_MEMORY_COUNTERS {
+            arrivals.retain(|_, arrival| *arrival > now);
+        }
+        let wait = match gcra(arrivals.get(key).copied(), now, emission_interval, window) {
+            Ok(arrival) => {
+                arrivals.insert(key.to_string(), arrival);
+                None
+            }
+            Err(wait) => Some(wait),
+        };
+        Box::pin(async move { Ok(wait) })
+    }
+}
+
+pub struct PostgresStore {
+    db_pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<i64, sqlx::Error>> {
+        Box::pin(async move {
+            let hits = sqlx::query!(
+                r#"
+INSERT INTO rate_limit_counters (key, window_start, hits, expires_at)
+VALUES ($1, $2, 1, $3)
+ON CONFLICT (key) DO UPDATE
+SET hits = CASE
+        WHEN rate_limit_counters.window_start = EXCLUDED.window_start
+        THEN rate_limit_counters.hits + 1
+        ELSE 1
+    END,
+    window_start = EXCLUDED.window_start,
+    expires_at = EXCLUDED.expires_at
+RETURNING hits
+"#,
+                key,
+                window_start,
+                expires_at
+            )
+            .fetch_one(&self.db_pool)
+            .await?
+            .hits;
+            // Clean up when a window starts rather than on every request.
+            if hits == 1 {
+                sqlx::query!(
+                    "DELETE FROM rate_limit_counters WHERE expires_at < $1",
+                    window_start
+                )
+                .execute(&self.db_pool)
+                .await?;
+            }
+            Ok(hits as i64)
+        })
+    }
+
+    fn arrive<'a>(
+        &'a self,
+        key: &'a str,
+        now: DateTime<Utc>,
+        emission_interval: Duration,
+        window: Duration,
+    ) -> BoxFuture<'a, Result<Option<Duration>, sqlx::Error>> {
+        Box::pin(async move {
+            // The same test as `gcra`, in one statement so that concurrent
+            // requests cannot both take the last slot.
+            let allowed = sqlx::query!(
+                r#"
+INSERT INTO rate_limit_arrivals (key, theoretical_arrival)
+VALUES ($1, $2::timestamptz + $3::bigint * interval '1 millisecond')
+ON CONFLICT (key) DO UPDATE
+SET theoretical_arrival =
+    GREATEST(rate_limit_arrivals.theoretical_arrival, $2) + $3 * interval '1 millisecond'
+WHERE GREATEST(rate_limit_arrivals.theoretical_arrival, $2)
+    + ($3 - $4::bigint) * interval '1 millisecond' <= $2
+RETURNING (xmax = 0) AS "inserted!"
+"#,
+                key,
+                now,
+                emission_interval.num_milliseconds(),
+                window.num_milliseconds()
+            )
+            .fetch_optional(&self.db_pool)
+            .await?;
+            match allowed {
+                Some(allowed) => {
+                    // Clean up when a client shows up rather than on every
+                    // request.
+                    if allowed.inserted {
+                        sqlx::query!(
+                            "DELETE FROM rate_limit_arrivals WHERE theoretical_arrival < $1",
+                            now
+                        )
+                        .execute(&self.db_pool)
+                        .await?;
+                    }
+                    Ok(None)
+                }
+                None => {
+                    let theoretical_arrival = sqlx::query!(
+                        "SELECT theoretical_arrival FROM rate_limit_arrivals WHERE key = $1",
+                        key
+                    )
+                    .fetch_one(&self.db_pool)
+                    .await?
+                    .theoretical_arrival;
+                    Ok(
+                        gcra(Some(theoretical_arrival), now, emission_interval, window)
+                            .err()
+                            .or(Some(Duration::zero())),
+                    )
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, window_start, InMemoryStore, RateLimitStore};
+    use actix_web::test::TestRequest;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn the_client_ip_is_taken_from_trusted_hops_only() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .append_header(("X-Forwarded-For", "6.6.6.6, 203.0.113.7"))
+            .append_header(("X-Forwarded-For", "172.16.0.2"))
+            .to_http_request();
+
+        assert_eq!(client_ip(&req, 0).unwrap().to_string(), "10.0.0.1");
+        assert_eq!(client_ip(&req, 1).unwrap().to_string(), "172.16.0.2");
+        assert_eq!(client_ip(&req, 2).unwrap().to_string(), "203.0.113.7");
+        // More hops than entries: the header is not what we expected.
+        assert_eq!(client_ip(&req, 4).unwrap().to_string(), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn counters_start_over_with_each_window() {
+        let store = InMemoryStore::default();
+        let now = Utc.with_ymd_and_hms(2023, 1, 17, 12, 0, 42).unwrap();
+        let first = window_start(now, 60);
+        let second = window_start(now + Duration::seconds(60), 60);
+        assert_eq!(first, Utc.with_ymd_and_hms(2023, 1, 17, 12, 0, 0).unwrap());
+
+        let hit = |window_start| {
+            store.hit(
+                "0:127.0.0.1",
+                window_start,
+                window_start + Duration::seconds(60),
+            )
+        };
+        assert_eq!(hit(first).await.unwrap(), 1);
+        assert_eq!(hit(first).await.unwrap(), 2);
+        assert_eq!(hit(second).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn gcra_allows_a_burst_then_one_request_per_emission_interval() {
+        let store = InMemoryStore::default();
+        let now = Utc.with_ymd_and_hms(2023, 1, 17, 12, 0, 42).unwrap();
+        // 3 requests a minute: one every 20 seconds.
+        let arrive = |now| {
+            store.arrive(
+                "0:127.0.0.1",
+                now,
+                Duration::seconds(20),
+                Duration::seconds(60),
+            )
+        };
+
+        for _ in 0..3 {
+            assert_eq!(arrive(now).await.unwrap(), None);
+        }
+        assert_eq!(arrive(now).await.unwrap(), Some(Duration::seconds(20)));
+        // Rejected requests do not push the next slot back.
+        let later = now + Duration::seconds(15);
+        assert_eq!(arrive(later).await.unwrap(), Some(Duration::seconds(5)));
+        let next_slot = now + Duration::seconds(20);
+        assert_eq!(arrive(next_slot).await.unwrap(), None);
+        assert_eq!(
+            arrive(next_slot).await.unwrap(),
+            Some(Duration::seconds(20))
+        );
+        // Unlike a fixed window, a new minute does not reset the limit.
+        let next_minute = Utc.with_ymd_and_hms(2023, 1, 17, 12, 1, 22).unwrap();
+        assert_eq!(arrive(next_minute).await.unwrap(), None);
+        assert_eq!(
+            arrive(next_minute).await.unwrap(),
+            Some(Duration::seconds(20))
+        );
+    }
+}
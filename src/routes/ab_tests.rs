@@ -0,0 +1,188 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::Admin,
+    domain::AbTest,
+    newsletter_scheduler::get_variant_results,
+    routes::{get_issue_status, PublishError},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AbTestData {
+    subjects: Vec<String>,
+    sample_percent: i16,
+    wait_minutes: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct AbTestReport {
+    pub newsletter_issue_id: Uuid,
+    pub sample_percent: i16,
+    pub wait_minutes: i32,
+    pub decide_at: Option<DateTime<Utc>>,
+    pub winning_variant: Option<i16>,
+    pub variants: Vec<VariantReport>,
+}
+
+#[derive(serde::Serialize)]
+pub struct VariantReport {
+    pub variant_index: i16,
+    pub subject: String,
+    pub sent: i64,
+    pub opens: i64,
+    pub open_rate: f64,
+}
+
+/// Set up an A/B test of subject lines for a draft, replacing any previous
+/// one. Picking a winner relies on opens, so tracking gets turned on.
+#[tracing::instrument(
+    name = "Configure the A/B test of a draft",
+    skip(_admin, body, db_pool)
+)]
+pub async fn configure_ab_test(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<AbTestData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let AbTestData {
+        subjects,
+        sample_percent,
+        wait_minutes,
+    } = body.into_inner();
+    let ab_test = AbTest::parse(subjects, sample_percent, wait_minutes)
+        .map_err(PublishError::ValidationError)?;
+
+    let mut transaction = db_pool.begin().await?;
+    let status = get_issue_status(&mut transaction, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    if !status.is_editable() {
+        return Err(PublishError::InvalidTransition(format!(
+            "A {} issue can no longer be edited.",
+            status.as_str()
+        )));
+    }
+    store_ab_test(&mut transaction, newsletter_issue_id, &ab_test).await?;
+    let report = get_ab_test_report(&mut transaction, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[tracing::instrument(name = "Get the results of an A/B test", skip(_admin, db_pool))]
+pub async fn ab_test_report(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let mut transaction = db_pool.begin().await?;
+    let report = get_ab_test_report(&mut transaction, *newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[tracing::instrument(skip(transaction, ab_test))]
+async fn store_ab_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    ab_test: &AbTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subject_variants WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+INSERT INTO ab_tests (newsletter_issue_id, sample_percent, wait_minutes)
+VALUES ($1, $2, $3)
+ON CONFLICT (newsletter_issue_id) DO UPDATE
+SET sample_percent = EXCLUDED.sample_percent, wait_minutes = EXCLUDED.wait_minutes
+"#,
+        newsletter_issue_id,
+        ab_test.sample_percent(),
+        ab_test.wait_minutes()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let variant_indexes: Vec<i16> = (0..ab_test.subjects().len() as i16).collect();
+    sqlx::query!(
+        r#"
+INSERT INTO subject_variants (newsletter_issue_id, variant_index, subject)
+SELECT $1, variant_index, subject
+FROM UNNEST($2::smallint[], $3::text[]) AS variants(variant_index, subject)
+"#,
+        newsletter_issue_id,
+        &variant_indexes,
+        ab_test.subjects()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET tracking_enabled = TRUE WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn get_ab_test_report(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<AbTestReport>, sqlx::Error> {
+    let ab_test = match sqlx::query!(
+        r#"
+SELECT sample_percent, wait_minutes, decide_at, winning_variant
+FROM ab_tests
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    {
+        Some(ab_test) => ab_test,
+        None => return Ok(None),
+    };
+    let subjects = sqlx::query!(
+        r#"
+SELECT subject FROM subject_variants
+WHERE newsletter_issue_id = $1
+ORDER BY variant_index
+"#,
+        newsletter_issue_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let results = get_variant_results(&mut *transaction, newsletter_issue_id).await?;
+    let variants = results
+        .into_iter()
+        .zip(subjects)
+        .map(|(result, r)| VariantReport {
+            variant_index: result.variant_index,
+            subject: r.subject,
+            sent: result.sent,
+            opens: result.opens,
+            open_rate: result.open_rate(),
+        })
+        .collect();
+    Ok(Some(AbTestReport {
+        newsletter_issue_id,
+        sample_percent: ab_test.sample_percent,
+        wait_minutes: ab_test.wait_minutes,
+        decide_at: ab_test.decide_at,
+        winning_variant: ab_test.winning_variant,
+        variants,
+    }))
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use actix_web::{http::header, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::Admin,
+    domain::{DeliveryStatus, IssueStatus},
+    routes::PublishError,
+};
+
+const LAST_ERRORS: i64 = 10;
+const STREAM_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(serde::Serialize)]
+pub struct DeliveryProgress {
+    pub newsletter_issue_id: Uuid,
+    pub issue_status: String,
+    pub total: i64,
+    pub queued: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub retrying: i64,
+    pub cancelled: i64,
+    pub held: i64,
+    /// Waiting to go out in the digests of subscribers on a digest cadence.
+    pub digest: i64,
+    pub estimated_seconds_remaining: Option<i64>,
+    pub last_errors: Vec<DeliveryError>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryError {
+    pub subscriber_id: Uuid,
+    pub status: String,
+    pub error: String,
+    pub occurred_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get the delivery progress of an issue", skip(_admin, db_pool))]
+pub async fn delivery_status(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let progress = get_delivery_progress(&db_pool, *newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+/// Server-sent events with the delivery progress of an issue, once a second
+/// until its delivery is over.
+#[tracing::instrument(
+    name = "Stream the delivery progress of an issue",
+    skip(_admin, db_pool)
+)]
+pub async fn delivery_status_stream(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let progress = get_delivery_progress(&db_pool, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+
+    let events = stream::unfold((Some(progress), false), move |(progress, is_over)| {
+        let db_pool = db_pool.clone();
+        async move {
+            if is_over {
+                return None;
+            }
+            let progress = match progress {
+                Some(progress) => Ok(Some(progress)),
+                None => {
+                    tokio::time::sleep(STREAM_INTERVAL).await;
+                    get_delivery_progress(&db_pool, newsletter_issue_id).await
+                }
+            };
+            let (event, is_over) = match progress {
+                Ok(Some(progress)) => (
+                    format!(
+                        "event: progress\ndata: {}\n\n",
+                        serde_json::to_string(&progress).unwrap()
+                    ),
+                    is_delivery_over(&progress),
+                ),
+                // The issue got erased, or we cannot tell how it is going.
+                Ok(None) | Err(_) => ("event: error\ndata: {}\n\n".to_string(), true),
+            };
+            Some((
+                Ok::<_, actix_web::Error>(web::Bytes::from(event)),
+                (None, is_over),
+            ))
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+fn is_delivery_over(progress: &DeliveryProgress) -> bool {
+    let status = IssueStatus::parse(progress.issue_status.clone())
+        .expect("A stored issue status is always valid.");
+    matches!(status, IssueStatus::Sent | IssueStatus::Cancelled)
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_delivery_progress(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DeliveryProgress>, sqlx::Error> {
+    let issue = match sqlx::query!(
+        r#"
+SELECT
+    status,
+    published_at,
+    (SELECT MAX(updated_at) FROM issue_delivery_queue WHERE newsletter_issue_id = $1) AS last_update
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await?
+    {
+        Some(issue) => issue,
+        None => return Ok(None),
+    };
+    let counts = sqlx::query!(
+        r#"
+SELECT status, COUNT(*) AS "count!"
+FROM issue_delivery_queue
+WHERE newsletter_issue_id = $1
+GROUP BY status
+"#,
+        newsletter_issue_id
+    )
+    .fetch_all(db_pool)
+    .await?;
+    let last_errors = sqlx::query_as!(
+        DeliveryError,
+        r#"
+SELECT subscriber_id, status, last_error AS "error!", updated_at AS occurred_at
+FROM issue_delivery_queue
+WHERE newsletter_issue_id = $1 AND last_error IS NOT NULL
+ORDER BY updated_at DESC NULLS LAST
+LIMIT $2
+"#,
+        newsletter_issue_id,
+        LAST_ERRORS
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    let count = |status: DeliveryStatus| {
+        counts
+            .iter()
+            .find(|r| r.status == status.as_str())
+            .map(|r| r.count)
+            .unwrap_or(0)
+    };
+    let pending = count(DeliveryStatus::Queued)
+        + count(DeliveryStatus::Retrying)
+        + count(DeliveryStatus::Held);
+    let processed = count(DeliveryStatus::Sent)
+        + count(DeliveryStatus::Failed)
+        + count(DeliveryStatus::Bounced);
+    let cancelled = count(DeliveryStatus::Cancelled);
+    let digest = count(DeliveryStatus::Digest);
+    // Extrapolate from how long the tasks processed so far took.
+    let estimated_seconds_remaining = match (issue.published_at, issue.last_update) {
+        _ if pending == 0 => Some(0),
+        // Nothing moves while the delivery is paused.
+        _ if issue.status == IssueStatus::Paused.as_str() => None,
+        (Some(published_at), Some(last_update)) if processed > 0 => {
+            let elapsed = (last_update - published_at).num_seconds().max(0);
+            Some(elapsed * pending / processed)
+        }
+        _ => None,
+    };
+
+    Ok(Some(DeliveryProgress {
+        newsletter_issue_id,
+        issue_status: issue.status,
+        total: pending + processed + cancelled + digest,
+        queued: count(DeliveryStatus::Queued),
+        sent: count(DeliveryStatus::Sent),
+        failed: count(DeliveryStatus::Failed),
+        bounced: count(DeliveryStatus::Bounced),
+        retrying: count(DeliveryStatus::Retrying),
+        cancelled,
+        held: count(DeliveryStatus::Held),
+        digest,
+        estimated_seconds_remaining,
+        last_errors,
+    }))
+}
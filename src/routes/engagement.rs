@@ -0,0 +1,217 @@
+use actix_web::{http::header, web, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::Admin,
+    routes::PublishError,
+    tracking::{parse_click_id, TRACKING_PIXEL},
+};
+
+#[derive(Clone, Copy, Debug)]
+enum EngagementKind {
+    Open,
+    Click,
+}
+
+impl EngagementKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EngagementKind::Open => "open",
+            EngagementKind::Click => "click",
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct EngagementReport {
+    pub recipients: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    pub open_rate: f64,
+    pub click_rate: f64,
+    pub links: Vec<LinkEngagement>,
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkEngagement {
+    /// The translation the link is in, none for the issue as written.
+    pub locale: Option<String>,
+    pub link_index: i32,
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+/// Serve the tracking pixel, recording an open for known tracking tokens.
+/// Unknown tokens get the same pixel, so the endpoint cannot be used to probe
+/// for valid ones.
+#[tracing::instrument(name = "Track an open", skip(db_pool))]
+pub async fn track_open(
+    tracking_token: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    // A missed open must not show a broken image in the email.
+    let _ = record_event(&db_pool, &tracking_token, EngagementKind::Open, None).await;
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(TRACKING_PIXEL)
+}
+
+#[tracing::instrument(name = "Track a click", skip(db_pool))]
+pub async fn track_click(click_id: web::Path<String>, db_pool: web::Data<PgPool>) -> HttpResponse {
+    let (tracking_token, link_index) = match parse_click_id(&click_id) {
+        Some(click) => click,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let url = match get_link(&db_pool, tracking_token, link_index).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Readers should reach the link even if recording the click failed.
+    let _ = record_event(
+        &db_pool,
+        tracking_token,
+        EngagementKind::Click,
+        Some(link_index),
+    )
+    .await;
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish()
+}
+
+/// Opens are counted for every recipient with an engagement event, since
+/// clicking a link implies having opened the issue even when images were
+/// blocked.
+#[tracing::instrument(name = "Report on the engagement with an issue", skip(_admin, db_pool))]
+pub async fn engagement_report(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let totals = sqlx::query!(
+        r#"
+SELECT
+    (SELECT COUNT(*) FROM issue_recipients WHERE newsletter_issue_id = $1) AS "recipients!",
+    (
+        SELECT COUNT(DISTINCT engagement_events.tracking_token)
+        FROM engagement_events
+        JOIN issue_recipients USING (tracking_token)
+        WHERE issue_recipients.newsletter_issue_id = $1
+    ) AS "unique_opens!",
+    (
+        SELECT COUNT(DISTINCT engagement_events.tracking_token)
+        FROM engagement_events
+        JOIN issue_recipients USING (tracking_token)
+        WHERE issue_recipients.newsletter_issue_id = $1 AND engagement_events.kind = $2
+    ) AS "unique_clicks!"
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        EngagementKind::Click.as_str()
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await?
+    .ok_or(PublishError::NotFound)?;
+    let links = sqlx::query_as!(
+        LinkEngagement,
+        r#"
+SELECT
+    NULLIF(issue_links.locale, '') AS locale,
+    issue_links.link_index,
+    issue_links.url,
+    COUNT(engagement_events.id) AS "clicks!",
+    COUNT(DISTINCT engagement_events.tracking_token) AS "unique_clicks!"
+FROM issue_links
+LEFT JOIN (
+    engagement_events JOIN issue_recipients USING (tracking_token)
+) ON issue_recipients.newsletter_issue_id = issue_links.newsletter_issue_id
+    AND issue_recipients.locale = issue_links.locale
+    AND engagement_events.link_index = issue_links.link_index
+    AND engagement_events.kind = $2
+WHERE issue_links.newsletter_issue_id = $1
+GROUP BY issue_links.locale, issue_links.link_index, issue_links.url
+ORDER BY issue_links.locale, issue_links.link_index
+"#,
+        newsletter_issue_id,
+        EngagementKind::Click.as_str()
+    )
+    .fetch_all(db_pool.get_ref())
+    .await?;
+
+    let rate = |count: i64| {
+        if totals.recipients == 0 {
+            0.0
+        } else {
+            count as f64 / totals.recipients as f64
+        }
+    };
+    Ok(HttpResponse::Ok().json(EngagementReport {
+        recipients: totals.recipients,
+        unique_opens: totals.unique_opens,
+        unique_clicks: totals.unique_clicks,
+        open_rate: rate(totals.unique_opens),
+        click_rate: rate(totals.unique_clicks),
+        links,
+    }))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn record_event(
+    db_pool: &PgPool,
+    tracking_token: &str,
+    kind: EngagementKind,
+    link_index: Option<i32>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO engagement_events (id, tracking_token, kind, link_index, occurred_at)
+SELECT $1, tracking_token, $3, $4, $5
+FROM issue_recipients
+WHERE tracking_token = $2
+"#,
+        Uuid::new_v4(),
+        tracking_token,
+        kind.as_str(),
+        link_index,
+        Utc::now()
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_link(
+    db_pool: &PgPool,
+    tracking_token: &str,
+    link_index: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+SELECT issue_links.url
+FROM issue_recipients
+JOIN issue_links USING (newsletter_issue_id, locale)
+WHERE issue_recipients.tracking_token = $1 AND issue_links.link_index = $2
+"#,
+        tracking_token,
+        link_index
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.url))
+}
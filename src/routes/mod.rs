@@ -1,9 +1,29 @@
+pub mod ab_tests;
+pub mod delivery_progress;
+pub mod engagement;
 pub mod health_check;
+pub mod newsletter_archive;
+pub mod newsletter_drafts;
 pub mod newsletters;
+pub mod scheduled_newsletters;
+pub mod subscriber_attributes;
+pub mod subscriber_data;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod subscriptions_preferences;
+pub mod subscriptions_unsubscribe;
 
+pub use ab_tests::*;
+pub use delivery_progress::*;
+pub use engagement::*;
 pub use health_check::*;
+pub use newsletter_archive::*;
+pub use newsletter_drafts::*;
 pub use newsletters::*;
+pub use scheduled_newsletters::*;
+pub use subscriber_attributes::*;
+pub use subscriber_data::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
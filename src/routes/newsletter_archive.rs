@@ -0,0 +1,225 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{startup::ApplicationBaseUrl, utils::html_escape};
+
+const ARCHIVE_PAGE_SIZE: i64 = 10;
+const FEED_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+struct ArchivedIssue {
+    title: String,
+    slug: String,
+    content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Show the newsletter archive", skip(parameters, db_pool))]
+pub async fn newsletter_archive(
+    parameters: web::Query<ArchiveParameters>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let page = parameters.page.unwrap_or(1).max(1);
+    // One extra row tells us whether there is a next page.
+    let mut issues = match get_published_issues(
+        &db_pool,
+        ARCHIVE_PAGE_SIZE + 1,
+        (page - 1) * ARCHIVE_PAGE_SIZE,
+    )
+    .await
+    {
+        Ok(issues) => issues,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let has_next_page = issues.len() as i64 > ARCHIVE_PAGE_SIZE;
+    issues.truncate(ARCHIVE_PAGE_SIZE as usize);
+
+    let entries: String = issues
+        .iter()
+        .map(|issue| {
+            format!(
+                r#"<li><a href="/newsletters/{}">{}</a> <time datetime="{}">{}</time></li>
+"#,
+                html_escape(&issue.slug),
+                html_escape(&issue.title),
+                issue.published_at.to_rfc3339(),
+                issue.published_at.format("%B %-d, %Y")
+            )
+        })
+        .collect();
+    let mut pagination = String::new();
+    if page > 1 {
+        pagination.push_str(&format!(
+            r#"<a href="/newsletters?page={}" rel="prev">Newer issues</a> "#,
+            page - 1
+        ));
+    }
+    if has_next_page {
+        pagination.push_str(&format!(
+            r#"<a href="/newsletters?page={}" rel="next">Older issues</a>"#,
+            page + 1
+        ));
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Newsletter archive</title>
+<link rel="alternate" type="application/atom+xml" href="/newsletters/feed.atom">
+</head>
+<body>
+<h1>Newsletter archive</h1>
+<ul>
+{}</ul>
+<nav>{}</nav>
+</body>
+</html>"#,
+            entries, pagination
+        ))
+}
+
+#[tracing::instrument(name = "Show a published newsletter issue", skip(db_pool))]
+pub async fn newsletter_web_view(
+    slug: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let issue = match get_published_issue(&db_pool, &slug).await {
+        Ok(issue) => issue,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match issue {
+        None => HttpResponse::NotFound().finish(),
+        Some(issue) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<article>
+<h1>{title}</h1>
+<time datetime="{}">{}</time>
+{}
+</article>
+<a href="/newsletters">All issues</a>
+</body>
+</html>"#,
+                issue.published_at.to_rfc3339(),
+                issue.published_at.format("%B %-d, %Y"),
+                issue.content,
+                title = html_escape(&issue.title),
+            )),
+    }
+}
+
+#[tracing::instrument(name = "Render the newsletter feed", skip(db_pool, base_url))]
+pub async fn newsletter_feed(
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let issues = match get_published_issues(&db_pool, FEED_SIZE, 0).await {
+        Ok(issues) => issues,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let base_url = &base_url.0;
+    let updated = issues
+        .first()
+        .map(|issue| issue.published_at)
+        .unwrap_or_else(Utc::now);
+
+    let entries: String = issues
+        .iter()
+        .map(|issue| {
+            let link = format!("{}/newsletters/{}", base_url, issue.slug);
+            format!(
+                r#"<entry>
+<title>{}</title>
+<link href="{link}"/>
+<id>{link}</id>
+<updated>{}</updated>
+<content type="html">{}</content>
+</entry>
+"#,
+                html_escape(&issue.title),
+                issue.published_at.to_rfc3339(),
+                html_escape(&issue.content),
+                link = html_escape(&link),
+            )
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>Newsletter archive</title>
+<link href="{base_url}/newsletters"/>
+<link rel="self" href="{base_url}/newsletters/feed.atom"/>
+<id>{base_url}/newsletters</id>
+<updated>{}</updated>
+{}</feed>"#,
+            updated.to_rfc3339(),
+            entries,
+            base_url = html_escape(base_url),
+        ))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_published_issues(
+    db_pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+SELECT title, slug AS "slug!", content, published_at AS "published_at!"
+FROM newsletter_issues
+WHERE published_at IS NOT NULL AND slug IS NOT NULL
+ORDER BY published_at DESC
+LIMIT $1
+OFFSET $2
+"#,
+        limit,
+        offset
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_published_issue(
+    db_pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+SELECT title, slug AS "slug!", content, published_at AS "published_at!"
+FROM newsletter_issues
+WHERE slug = $1 AND published_at IS NOT NULL
+"#,
+        slug
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
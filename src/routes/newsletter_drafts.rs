@@ -0,0 +1,464 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::Admin,
+    domain::{SendAt, SubscriberEmail},
+    email_client::EmailClient,
+    i18n::Locale,
+    issue_delivery_worker::render_issue,
+    routes::{
+        attach_lists, get_issue_status, get_list_ids, insert_draft, insert_variants,
+        parse_variants, publish_issue, validate_segment, IssueVariant, PublishError,
+        PublishedIssue, DEFAULT_LIST,
+    },
+    startup::ApplicationBaseUrl,
+};
+
+/// Stands in for a subscription token in previews and test sends, which are
+/// not addressed to a subscriber.
+const SAMPLE_SUBSCRIPTION_TOKEN: &str = "sample";
+
+#[derive(serde::Deserialize)]
+pub struct DraftData {
+    title: String,
+    content: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    tracking: bool,
+    segment: Option<String>,
+    #[serde(default)]
+    variants: Vec<IssueVariant>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftEdit {
+    title: Option<String>,
+    content: Option<String>,
+    lists: Option<Vec<String>>,
+    tracking: Option<bool>,
+    /// An empty segment removes the one the draft had.
+    segment: Option<String>,
+    /// Replaces every variant the draft had.
+    variants: Option<Vec<IssueVariant>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewParameters {
+    subscriber_email: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestSendData {
+    email: String,
+    subscriber_email: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishData {
+    send_at: Option<String>,
+    timezone: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueDetails {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub status: String,
+    pub lists: Vec<String>,
+    pub send_at: Option<DateTime<Utc>>,
+    pub timezone: Option<String>,
+    pub tracking_enabled: bool,
+    pub segment: Option<String>,
+    pub variants: Json<Vec<IssueVariant>>,
+}
+
+impl IssueDetails {
+    /// The title and content subscribers in `locale` get: the variant in
+    /// their language, or the issue as written.
+    fn for_locale(&self, locale: &str) -> (&str, &str) {
+        self.variants
+            .iter()
+            .find(|variant| variant.locale == locale)
+            .map_or((&self.title, &self.content), |variant| {
+                (&variant.title, &variant.content)
+            })
+    }
+}
+
+#[tracing::instrument(name = "Create a newsletter draft", skip(_admin, body, db_pool))]
+pub async fn create_draft(
+    _admin: Admin,
+    body: web::Json<DraftData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let DraftData {
+        title,
+        content,
+        mut lists,
+        tracking,
+        segment,
+        variants,
+    } = body.into_inner();
+    if lists.is_empty() {
+        lists.push(DEFAULT_LIST.into());
+    }
+    validate_segment(segment.as_deref())?;
+    let variants = parse_variants(variants)?;
+
+    let mut transaction = db_pool.begin().await?;
+    let list_ids = get_list_ids(&mut transaction, &lists).await?;
+    let newsletter_issue_id = insert_draft(
+        &mut transaction,
+        &title,
+        &content,
+        tracking,
+        segment.as_deref(),
+    )
+    .await?;
+    attach_lists(&mut transaction, newsletter_issue_id, &list_ids).await?;
+    insert_variants(&mut transaction, newsletter_issue_id, &variants).await?;
+    transaction.commit().await?;
+
+    let issue = get_issue_details(&db_pool, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    Ok(HttpResponse::Created().json(issue))
+}
+
+#[tracing::instrument(name = "List newsletter drafts", skip(_admin, db_pool))]
+pub async fn list_drafts(
+    _admin: Admin,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let drafts = sqlx::query_as!(
+        IssueDetails,
+        r#"
+SELECT
+    newsletter_issues.newsletter_issue_id,
+    newsletter_issues.title,
+    newsletter_issues.content,
+    newsletter_issues.status,
+    COALESCE(array_agg(lists.slug ORDER BY lists.slug) FILTER (WHERE lists.slug IS NOT NULL), '{}') AS "lists!",
+    newsletter_issues.send_at,
+    newsletter_issues.timezone,
+    newsletter_issues.tracking_enabled,
+    newsletter_issues.segment,
+    COALESCE(
+        (
+            SELECT json_agg(
+                json_build_object('locale', locale, 'title', title, 'content', content)
+                ORDER BY locale
+            )
+            FROM newsletter_issue_variants
+            WHERE newsletter_issue_variants.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+        ),
+        '[]'
+    ) AS "variants!: Json<Vec<IssueVariant>>"
+FROM newsletter_issues
+LEFT JOIN newsletter_issue_lists USING (newsletter_issue_id)
+LEFT JOIN lists ON lists.id = newsletter_issue_lists.list_id
+WHERE newsletter_issues.status = 'draft'
+GROUP BY newsletter_issues.newsletter_issue_id
+ORDER BY newsletter_issues.created_at
+"#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await?;
+    Ok(HttpResponse::Ok().json(drafts))
+}
+
+#[tracing::instrument(name = "Get a newsletter issue", skip(_admin, db_pool))]
+pub async fn get_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let issue = get_issue_details(&db_pool, *newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+#[tracing::instrument(name = "Edit a newsletter draft", skip(_admin, body, db_pool))]
+pub async fn edit_draft(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<DraftEdit>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let DraftEdit {
+        title,
+        content,
+        lists,
+        tracking,
+        segment,
+        variants,
+    } = body.into_inner();
+    validate_segment(segment.as_deref().filter(|s| !s.trim().is_empty()))?;
+    let variants = variants.map(parse_variants).transpose()?;
+
+    let mut transaction = db_pool.begin().await?;
+    let status = get_issue_status(&mut transaction, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    if !status.is_editable() {
+        return Err(PublishError::InvalidTransition(format!(
+            "A {} issue can no longer be edited.",
+            status.as_str()
+        )));
+    }
+    sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET title = COALESCE($2, title),
+    content = COALESCE($3, content),
+    tracking_enabled = COALESCE($4, tracking_enabled),
+    segment = CASE WHEN $5::text IS NULL THEN segment ELSE NULLIF(trim($5), '') END
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        title,
+        content,
+        tracking,
+        segment
+    )
+    .execute(&mut transaction)
+    .await?;
+    if let Some(lists) = lists {
+        replace_lists(&mut transaction, newsletter_issue_id, &lists).await?;
+    }
+    if let Some(variants) = variants {
+        sqlx::query!(
+            r#"DELETE FROM newsletter_issue_variants WHERE newsletter_issue_id = $1"#,
+            newsletter_issue_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        insert_variants(&mut transaction, newsletter_issue_id, &variants).await?;
+    }
+    transaction.commit().await?;
+
+    let issue = get_issue_details(&db_pool, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+#[tracing::instrument(
+    name = "Preview a newsletter issue",
+    skip(_admin, parameters, db_pool, base_url)
+)]
+pub async fn preview_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = get_issue_details(&db_pool, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    let locale = sample_locale(
+        &db_pool,
+        newsletter_issue_id,
+        parameters.into_inner().subscriber_email,
+    )
+    .await?;
+    let (_, content) = issue.for_locale(&locale);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_issue(
+            content,
+            &base_url.0,
+            None,
+            SAMPLE_SUBSCRIPTION_TOKEN,
+            Locale::from_stored(&locale),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Send a test of a newsletter issue",
+    skip(_admin, body, db_pool, email_client, base_url)
+)]
+pub async fn send_test_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<TestSendData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PublishError> {
+    let TestSendData {
+        email,
+        subscriber_email,
+    } = body.into_inner();
+    let recipient =
+        SubscriberEmail::parse(email).map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let issue = get_issue_details(&db_pool, *newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    let locale = sample_locale(&db_pool, *newsletter_issue_id, subscriber_email).await?;
+    let (title, content) = issue.for_locale(&locale);
+
+    email_client
+        .send_email(
+            recipient,
+            &format!("[Test] {}", title),
+            "text/html",
+            &render_issue(
+                content,
+                &base_url.0,
+                None,
+                SAMPLE_SUBSCRIPTION_TOKEN,
+                Locale::from_stored(&locale),
+            ),
+        )
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(name = "Publish a newsletter draft", skip(_admin, body, db_pool))]
+pub async fn publish_draft(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<PublishData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let PublishData { send_at, timezone } = body.into_inner();
+    let send_at = send_at
+        .map(|send_at| SendAt::parse(send_at, timezone))
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+
+    let mut transaction = db_pool.begin().await?;
+    let status = publish_issue(&mut transaction, newsletter_issue_id, send_at.as_ref()).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id,
+        status: status.as_str().into(),
+        send_at: send_at.map(|send_at| send_at.time()),
+    }))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_issue_details(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueDetails,
+        r#"
+SELECT
+    newsletter_issues.newsletter_issue_id,
+    newsletter_issues.title,
+    newsletter_issues.content,
+    newsletter_issues.status,
+    COALESCE(array_agg(lists.slug ORDER BY lists.slug) FILTER (WHERE lists.slug IS NOT NULL), '{}') AS "lists!",
+    newsletter_issues.send_at,
+    newsletter_issues.timezone,
+    newsletter_issues.tracking_enabled,
+    newsletter_issues.segment,
+    COALESCE(
+        (
+            SELECT json_agg(
+                json_build_object('locale', locale, 'title', title, 'content', content)
+                ORDER BY locale
+            )
+            FROM newsletter_issue_variants
+            WHERE newsletter_issue_variants.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+        ),
+        '[]'
+    ) AS "variants!: Json<Vec<IssueVariant>>"
+FROM newsletter_issues
+LEFT JOIN newsletter_issue_lists USING (newsletter_issue_id)
+LEFT JOIN lists ON lists.id = newsletter_issue_lists.list_id
+WHERE newsletter_issues.newsletter_issue_id = $1
+GROUP BY newsletter_issues.newsletter_issue_id
+"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn replace_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    lists: &[String],
+) -> Result<(), PublishError> {
+    if lists.is_empty() {
+        return Err(PublishError::ValidationError(
+            "An issue has to target at least one list.".into(),
+        ));
+    }
+    let list_ids = get_list_ids(&mut *transaction, lists).await?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issue_lists WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    attach_lists(transaction, newsletter_issue_id, &list_ids).await?;
+    Ok(())
+}
+
+/// The locale previews and test sends are rendered in: the one of the given
+/// subscriber, or of the longest standing member of the issue's lists.
+async fn sample_locale(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: Option<String>,
+) -> Result<String, PublishError> {
+    let subscriber_email = subscriber_email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let locale = get_sample_locale(db_pool, newsletter_issue_id, subscriber_email.as_ref()).await?;
+    match (locale, subscriber_email) {
+        (Some(locale), _) => Ok(locale),
+        (None, None) => Ok(Locale::default().as_str().into()),
+        (None, Some(subscriber_email)) => Err(PublishError::ValidationError(format!(
+            "{} is not a confirmed member of the issue's lists.",
+            subscriber_email.as_ref()
+        ))),
+    }
+}
+
+/// The locale of a confirmed member of the issue's lists. Without an email,
+/// the longest standing member is picked.
+#[tracing::instrument(skip(db_pool))]
+async fn get_sample_locale(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: Option<&SubscriberEmail>,
+) -> Result<Option<String>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+SELECT subscriptions.locale
+FROM newsletter_issue_lists
+JOIN list_memberships ON list_memberships.list_id = newsletter_issue_lists.list_id
+JOIN subscriptions ON subscriptions.id = list_memberships.subscriber_id
+WHERE newsletter_issue_lists.newsletter_issue_id = $1
+    AND list_memberships.status = 'confirmed'
+    AND ($2::text IS NULL OR subscriptions.email = $2)
+ORDER BY subscriptions.subscribed_at
+LIMIT 1
+"#,
+        newsletter_issue_id,
+        subscriber_email.map(AsRef::as_ref)
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(r.map(|r| r.locale))
+}
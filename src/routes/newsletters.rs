@@ -1,5 +1,313 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
-pub async fn publish_newsletter() -> HttpResponse {
-    HttpResponse::Ok().finish()
+use crate::{
+    authentication::Admin,
+    domain::{IssueStatus, Segment, SendAt},
+    i18n::Locale,
+    newsletter_scheduler::start_delivery,
+    routes::DEFAULT_LIST,
+};
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    send_at: Option<String>,
+    timezone: Option<String>,
+    /// Opt the issue into open and click tracking.
+    #[serde(default)]
+    tracking: bool,
+    /// Only send to the members of the lists matching this filter expression.
+    segment: Option<String>,
+    #[serde(default)]
+    variants: Vec<IssueVariant>,
+}
+
+/// The issue in another language, sent to the subscribers using it instead
+/// of the issue as written.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct IssueVariant {
+    pub locale: String,
+    pub title: String,
+    pub content: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishedIssue {
+    pub newsletter_issue_id: Uuid,
+    pub status: String,
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Publish a newsletter issue", skip(_admin, body, db_pool))]
+pub async fn publish_newsletter(
+    _admin: Admin,
+    body: web::Json<BodyData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let BodyData {
+        title,
+        content,
+        mut lists,
+        send_at,
+        timezone,
+        tracking,
+        segment,
+        variants,
+    } = body.into_inner();
+    if lists.is_empty() {
+        lists.push(DEFAULT_LIST.into());
+    }
+    validate_segment(segment.as_deref())?;
+    let variants = parse_variants(variants)?;
+    let send_at = send_at
+        .map(|send_at| SendAt::parse(send_at, timezone))
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+
+    let mut transaction = db_pool.begin().await?;
+    let list_ids = get_list_ids(&mut transaction, &lists).await?;
+    let newsletter_issue_id = insert_draft(
+        &mut transaction,
+        &title,
+        &content,
+        tracking,
+        segment.as_deref(),
+    )
+    .await?;
+    attach_lists(&mut transaction, newsletter_issue_id, &list_ids).await?;
+    insert_variants(&mut transaction, newsletter_issue_id, &variants).await?;
+    let status = publish_issue(&mut transaction, newsletter_issue_id, send_at.as_ref()).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id,
+        status: status.as_str().into(),
+        send_at: send_at.map(|send_at| send_at.time()),
+    }))
+}
+
+/// Move a draft forward: schedule it when a send time is given, start its
+/// delivery otherwise.
+#[tracing::instrument(skip(transaction, send_at))]
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    send_at: Option<&SendAt>,
+) -> Result<IssueStatus, PublishError> {
+    let status = get_issue_status(&mut *transaction, newsletter_issue_id)
+        .await?
+        .ok_or(PublishError::NotFound)?;
+    match send_at {
+        Some(send_at) => {
+            let status = status
+                .transition_to(IssueStatus::Scheduled)
+                .map_err(PublishError::InvalidTransition)?;
+            sqlx::query!(
+                r#"
+UPDATE newsletter_issues SET status = $2, send_at = $3, timezone = $4
+WHERE newsletter_issue_id = $1
+"#,
+                newsletter_issue_id,
+                status.as_str(),
+                send_at.time(),
+                send_at.timezone()
+            )
+            .execute(&mut *transaction)
+            .await?;
+            Ok(status)
+        }
+        None => {
+            status
+                .transition_to(IssueStatus::Sending)
+                .map_err(PublishError::InvalidTransition)?;
+            Ok(start_delivery(transaction, newsletter_issue_id).await?)
+        }
+    }
+}
+
+/// Read the status of an issue, locking it until the end of the transaction.
+#[tracing::instrument(skip(transaction))]
+pub async fn get_issue_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueStatus>, sqlx::Error> {
+    let status = sqlx::query!(
+        r#"SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1 FOR UPDATE"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(transaction)
+    .await?
+    .map(|r| IssueStatus::parse(r.status).expect("A stored issue status is always valid."));
+    Ok(status)
+}
+
+#[tracing::instrument(name = "Get the target list ids", skip(transaction))]
+pub async fn get_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    lists: &[String],
+) -> Result<Vec<Uuid>, PublishError> {
+    let known = sqlx::query!(r#"SELECT id, slug FROM lists WHERE slug = ANY($1)"#, lists)
+        .fetch_all(&mut *transaction)
+        .await?;
+    match lists
+        .iter()
+        .find(|list| !known.iter().any(|r| &r.slug == *list))
+    {
+        Some(unknown) => Err(PublishError::ValidationError(format!(
+            "{} is not a known list.",
+            unknown
+        ))),
+        None => Ok(known.into_iter().map(|r| r.id).collect()),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    content: &str,
+    tracking_enabled: bool,
+    segment: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_issues (
+    newsletter_issue_id, title, content, status, tracking_enabled, segment, created_at
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+"#,
+        newsletter_issue_id,
+        title,
+        content,
+        IssueStatus::Draft.as_str(),
+        tracking_enabled,
+        segment,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Segments are stored as written, once we know they compile.
+pub fn validate_segment(segment: Option<&str>) -> Result<(), PublishError> {
+    if let Some(segment) = segment {
+        Segment::parse(segment).map_err(PublishError::ValidationError)?;
+    }
+    Ok(())
+}
+
+/// Variants with their locale in its canonical form, at most one per locale.
+pub fn parse_variants(variants: Vec<IssueVariant>) -> Result<Vec<IssueVariant>, PublishError> {
+    let mut parsed: Vec<IssueVariant> = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let locale = Locale::parse(&variant.locale).map_err(PublishError::ValidationError)?;
+        if parsed.iter().any(|v| v.locale == locale.as_str()) {
+            return Err(PublishError::ValidationError(format!(
+                "There is more than one variant for {}.",
+                locale.as_str()
+            )));
+        }
+        parsed.push(IssueVariant {
+            locale: locale.as_str().into(),
+            ..variant
+        });
+    }
+    Ok(parsed)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_variants(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    variants: &[IssueVariant],
+) -> Result<(), sqlx::Error> {
+    let locales: Vec<&str> = variants.iter().map(|v| v.locale.as_str()).collect();
+    let titles: Vec<&str> = variants.iter().map(|v| v.title.as_str()).collect();
+    let contents: Vec<&str> = variants.iter().map(|v| v.content.as_str()).collect();
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_issue_variants (newsletter_issue_id, locale, title, content)
+SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::text[])
+"#,
+        newsletter_issue_id,
+        &locales as &[&str],
+        &titles as &[&str],
+        &contents as &[&str]
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn attach_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+"#,
+        newsletter_issue_id,
+        list_ids
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum PublishError {
+    ValidationError(String),
+    NotFound,
+    /// The issue is not in a state that allows the requested change.
+    InvalidTransition(String),
+    DatabaseError(sqlx::Error),
+    SendEmailError(reqwest::Error),
+}
+
+impl std::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublishError::NotFound => write!(f, "There is no such newsletter issue."),
+            PublishError::InvalidTransition(e) => write!(f, "{}", e),
+            _ => write!(f, "Failed to publish a newsletter issue."),
+        }
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::NotFound => StatusCode::NOT_FOUND,
+            PublishError::InvalidTransition(_) => StatusCode::CONFLICT,
+            PublishError::DatabaseError(_) | PublishError::SendEmailError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl From<sqlx::Error> for PublishError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<reqwest::Error> for PublishError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::SendEmailError(e)
+    }
 }
@@ -0,0 +1,206 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::Admin,
+    domain::{DeliveryStatus, IssueStatus, SendAt},
+    issue_delivery_worker::mark_issue_as_sent_if_done,
+    routes::get_issue_status,
+};
+
+#[derive(serde::Serialize)]
+pub struct ScheduledIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub send_at: Option<DateTime<Utc>>,
+    pub timezone: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleData {
+    send_at: String,
+    timezone: Option<String>,
+}
+
+#[tracing::instrument(name = "List scheduled newsletter issues", skip(_admin, db_pool))]
+pub async fn list_scheduled_newsletters(
+    _admin: Admin,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ScheduleError> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+SELECT newsletter_issue_id, title, send_at, timezone
+FROM newsletter_issues
+WHERE status = 'scheduled'
+ORDER BY send_at
+"#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await?;
+    Ok(HttpResponse::Ok().json(issues))
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(_admin, body, db_pool))]
+pub async fn reschedule_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<RescheduleData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ScheduleError> {
+    let RescheduleData { send_at, timezone } = body.into_inner();
+    let send_at = SendAt::parse(send_at, timezone).map_err(ScheduleError::ValidationError)?;
+    let issue = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+UPDATE newsletter_issues SET send_at = $2, timezone = $3
+WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+RETURNING newsletter_issue_id, title, send_at, timezone
+"#,
+        *newsletter_issue_id,
+        send_at.time(),
+        send_at.timezone()
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await?
+    .ok_or(ScheduleError::NotScheduled)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Cancel an issue that is not sent yet. What is left of an in-flight
+/// delivery is marked as cancelled.
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(_admin, db_pool))]
+pub async fn cancel_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ScheduleError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut transaction = db_pool.begin().await?;
+    transition_issue(
+        &mut transaction,
+        newsletter_issue_id,
+        IssueStatus::Cancelled,
+    )
+    .await?;
+    // Tasks held by a worker are skipped rather than waited for: the worker
+    // locks the issue after them, and cancels them itself if they are not
+    // delivered, see `try_execute_task`.
+    sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue SET status = $2, updated_at = $3
+WHERE (newsletter_issue_id, subscriber_id) IN (
+    SELECT newsletter_issue_id, subscriber_id
+    FROM issue_delivery_queue
+    WHERE newsletter_issue_id = $1 AND status IN ('queued', 'retrying', 'held', 'digest')
+    FOR UPDATE
+    SKIP LOCKED
+)
+"#,
+        newsletter_issue_id,
+        DeliveryStatus::Cancelled.as_str(),
+        Utc::now()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Stop the workers from picking up more of the issue's delivery.
+#[tracing::instrument(
+    name = "Pause the delivery of a newsletter issue",
+    skip(_admin, db_pool)
+)]
+pub async fn pause_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ScheduleError> {
+    let mut transaction = db_pool.begin().await?;
+    transition_issue(&mut transaction, *newsletter_issue_id, IssueStatus::Paused).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(
+    name = "Resume the delivery of a newsletter issue",
+    skip(_admin, db_pool)
+)]
+pub async fn resume_newsletter(
+    _admin: Admin,
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ScheduleError> {
+    let mut transaction = db_pool.begin().await?;
+    transition_issue(&mut transaction, *newsletter_issue_id, IssueStatus::Sending).await?;
+    // The tasks workers held when the issue was paused may have been the last
+    // ones.
+    mark_issue_as_sent_if_done(&mut transaction, *newsletter_issue_id).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Move an issue to `next`, if its lifecycle allows it. The issue stays locked
+/// until the transaction ends.
+#[tracing::instrument(skip(transaction))]
+async fn transition_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    next: IssueStatus,
+) -> Result<(), ScheduleError> {
+    let status = get_issue_status(&mut *transaction, newsletter_issue_id)
+        .await?
+        .ok_or(ScheduleError::NotFound)?
+        .transition_to(next)
+        .map_err(ScheduleError::InvalidTransition)?;
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET status = $2 WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+        status.as_str()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    ValidationError(String),
+    /// The issue does not exist or its delivery already started.
+    NotScheduled,
+    NotFound,
+    InvalidTransition(String),
+    DatabaseError(sqlx::Error),
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::NotScheduled => write!(f, "There is no such scheduled issue."),
+            ScheduleError::NotFound => write!(f, "There is no such newsletter issue."),
+            ScheduleError::InvalidTransition(e) => write!(f, "{}", e),
+            _ => write!(f, "Failed to update a scheduled issue."),
+        }
+    }
+}
+
+impl ResponseError for ScheduleError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScheduleError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ScheduleError::NotScheduled | ScheduleError::NotFound => StatusCode::NOT_FOUND,
+            ScheduleError::InvalidTransition(_) => StatusCode::CONFLICT,
+            ScheduleError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<sqlx::Error> for ScheduleError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
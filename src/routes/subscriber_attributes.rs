@@ -0,0 +1,107 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::Admin;
+
+#[derive(serde::Serialize)]
+pub struct SubscriberAttributes {
+    pub subscriber_id: Uuid,
+    pub custom_attributes: serde_json::Value,
+}
+
+/// Merge custom attributes into those of a subscriber, for segments to
+/// filter on. Attributes set to `null` are removed.
+#[tracing::instrument(
+    name = "Update the custom attributes of a subscriber",
+    skip(_admin, body, db_pool)
+)]
+pub async fn update_subscriber_attributes(
+    _admin: Admin,
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<serde_json::Value>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AttributesError> {
+    let attributes = body.into_inner();
+    validate_attributes(&attributes).map_err(AttributesError::ValidationError)?;
+    let custom_attributes = sqlx::query!(
+        r#"
+UPDATE subscriptions SET custom_attributes = jsonb_strip_nulls(custom_attributes || $2)
+WHERE id = $1
+RETURNING custom_attributes
+"#,
+        *subscriber_id,
+        attributes
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .ok_or(AttributesError::NotFound)?
+    .custom_attributes;
+    Ok(HttpResponse::Ok().json(SubscriberAttributes {
+        subscriber_id: *subscriber_id,
+        custom_attributes,
+    }))
+}
+
+/// Attributes are flat: their names are those segments can refer to, their
+/// values text, numbers or booleans.
+fn validate_attributes(attributes: &serde_json::Value) -> Result<(), String> {
+    let attributes = attributes
+        .as_object()
+        .ok_or("Custom attributes have to be a JSON object.")?;
+    for (key, value) in attributes {
+        if key.is_empty()
+            || key.len() > 64
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("{} is not a valid attribute name.", key));
+        }
+        if value.is_array() || value.is_object() {
+            return Err(format!(
+                "The value of {} has to be text, a number or a boolean.",
+                key
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum AttributesError {
+    ValidationError(String),
+    NotFound,
+    DatabaseError(sqlx::Error),
+}
+
+impl std::fmt::Display for AttributesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributesError::ValidationError(e) => write!(f, "{}", e),
+            AttributesError::NotFound => write!(f, "There is no such subscriber."),
+            AttributesError::DatabaseError(_) => {
+                write!(f, "Failed to update the attributes of a subscriber.")
+            }
+        }
+    }
+}
+
+impl ResponseError for AttributesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AttributesError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            AttributesError::NotFound => StatusCode::NOT_FOUND,
+            AttributesError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<sqlx::Error> for AttributesError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
@@ -0,0 +1,721 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{EmailError, SubscriberEmail},
+    email_client::EmailClient,
+    routes::{generate_subscription_token, get_subscriber_id_from_email},
+    startup::ApplicationBaseUrl,
+    utils::html_escape,
+};
+
+#[derive(Clone, Copy, Debug)]
+enum DataRequestKind {
+    Export,
+    Erasure,
+}
+
+impl DataRequestKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "export",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+
+    fn link_path(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "/subscriptions/data_export",
+            DataRequestKind::Erasure => "/subscriptions/erasure",
+        }
+    }
+
+    fn subject(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "Your data export",
+            DataRequestKind::Erasure => "Confirm your data erasure",
+        }
+    }
+
+    fn html_body(&self, link: &str) -> String {
+        match self {
+            DataRequestKind::Export => format!(
+                "We received a request to export the data we store about you.<br />\
+                Click <a href=\"{}\">here</a> to download it.",
+                link
+            ),
+            DataRequestKind::Erasure => format!(
+                "We received a request to erase the data we store about you.<br />\
+                Click <a href=\"{}\">here</a> to confirm the erasure.",
+                link
+            ),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestForm {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestParameters {
+    data_request_token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    pub subscriber: SubscriberRecord,
+    pub subscription_tokens: Vec<String>,
+    pub list_memberships: Vec<ListMembershipRecord>,
+    pub consent_events: Vec<ConsentEventRecord>,
+    pub preference_changes: Vec<PreferenceChangeRecord>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub issues_received: Vec<IssueRecipientRecord>,
+    pub engagement_events: Vec<EngagementEventRecord>,
+    pub digests: Vec<DigestRecord>,
+    pub onboarding: Option<OnboardingRecord>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub email_original: String,
+    pub name: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub status: String,
+    pub delivery_cadence: String,
+    pub locale: String,
+    pub custom_attributes: serde_json::Value,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListMembershipRecord {
+    pub list: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEventRecord {
+    pub event_type: String,
+    pub list: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    pub consent_text_version: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct PreferenceChangeRecord {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// An issue queued for the subscriber, whether it was delivered or not.
+#[derive(serde::Serialize)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub n_retries: i16,
+    pub last_error: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueRecipientRecord {
+    pub newsletter_issue_id: Uuid,
+    pub tracking_token: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EngagementEventRecord {
+    pub newsletter_issue_id: Uuid,
+    pub kind: String,
+    pub link_index: Option<i32>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DigestRecord {
+    pub id: Uuid,
+    pub n_issues: i32,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct OnboardingRecord {
+    pub started_at: DateTime<Utc>,
+    pub next_step: i32,
+    pub status: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Request a data export",
+    skip(form, db_pool, email_client, base_url)
+)]
+pub async fn request_data_export(
+    form: web::Form<DataRequestForm>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, DataRequestError> {
+    let email = SubscriberEmail::parse(form.0.email)?;
+    start_data_request(
+        &db_pool,
+        &email_client,
+        &base_url.0,
+        email,
+        DataRequestKind::Export,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(parameters, db_pool))]
+pub async fn export_subscriber_data(
+    parameters: web::Query<DataRequestParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DataRequestError> {
+    let mut transaction = db_pool.begin().await?;
+    let subscriber_id = redeem_data_request_token(
+        &mut transaction,
+        &parameters.data_request_token,
+        DataRequestKind::Export,
+    )
+    .await?
+    .ok_or(DataRequestError::UnknownToken)?;
+    let export = get_subscriber_data(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(export))
+}
+
+#[tracing::instrument(
+    name = "Request a data erasure",
+    skip(form, db_pool, email_client, base_url)
+)]
+pub async fn request_data_erasure(
+    form: web::Form<DataRequestForm>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, DataRequestError> {
+    let email = SubscriberEmail::parse(form.0.email)?;
+    start_data_request(
+        &db_pool,
+        &email_client,
+        &base_url.0,
+        email,
+        DataRequestKind::Erasure,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The link in the erasure email only renders a confirmation form, so that
+/// mail scanners prefetching links cannot erase a subscriber on their own.
+pub async fn erasure_form(parameters: web::Query<DataRequestParameters>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Erase your data</title></head>
+<body>
+<p>This permanently removes your subscription and everything we store about you.</p>
+<form action="/subscriptions/erasure/confirm" method="post">
+<input type="hidden" name="data_request_token" value="{}">
+<button type="submit">Erase my data</button>
+</form>
+</body>
+</html>"#,
+            html_escape(&parameters.data_request_token)
+        ))
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, db_pool))]
+pub async fn erase_subscriber_data(
+    form: web::Form<DataRequestParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DataRequestError> {
+    let mut transaction = db_pool.begin().await?;
+    let subscriber_id = redeem_data_request_token(
+        &mut transaction,
+        &form.data_request_token,
+        DataRequestKind::Erasure,
+    )
+    .await?
+    .ok_or(DataRequestError::UnknownToken)?;
+    erase_subscriber(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Hash stored in `suppressed_emails`, so that we remember an erased address
+/// without keeping the address itself. Takes the address as stored, which
+/// rows older than the current validation rules may not have normalized: its
+/// domain is lowercased, its local part is kept as is like `SubscriberEmail`
+/// does.
+pub fn suppression_hash(email: &str) -> String {
+    let email = email.trim();
+    let normalized = match email.rsplit_once('@') {
+        Some((local_part, domain)) => format!("{}@{}", local_part, domain.to_lowercase()),
+        None => email.to_string(),
+    };
+    let digest = Sha256::digest(normalized.as_bytes());
+    format!("{:x}", digest)
+}
+
+#[tracing::instrument(name = "Check if an email is suppressed", skip(transaction, email))]
+pub async fn is_suppressed(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT email_hash FROM suppressed_emails WHERE email_hash = $1"#,
+        suppression_hash(email.as_ref())
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.is_some())
+}
+
+async fn start_data_request(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: SubscriberEmail,
+    kind: DataRequestKind,
+) -> Result<(), DataRequestError> {
+    let mut transaction = db_pool.begin().await?;
+    // Unknown addresses get the same response as known ones, so that the
+    // endpoint cannot be used to find out who is subscribed.
+    let subscriber_id = match get_subscriber_id_from_email(&mut transaction, &email).await? {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let data_request_token = generate_subscription_token();
+    store_data_request_token(&mut transaction, subscriber_id, &data_request_token, kind).await?;
+    transaction.commit().await?;
+
+    let link = format!(
+        "{}{}?data_request_token={}",
+        base_url,
+        kind.link_path(),
+        data_request_token
+    );
+    email_client
+        .send_email(email, kind.subject(), "text/html", &kind.html_body(&link))
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Store data request token in the database",
+    skip(transaction, data_request_token)
+)]
+async fn store_data_request_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    data_request_token: &str,
+    kind: DataRequestKind,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO data_request_tokens (data_request_token, subscriber_id, kind, requested_at)
+VALUES ($1, $2, $3, $4)
+"#,
+        data_request_token,
+        subscriber_id,
+        kind.as_str(),
+        Utc::now()
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Tokens are single-use and expire after a day.
+#[tracing::instrument(
+    name = "Redeem data request token",
+    skip(transaction, data_request_token)
+)]
+async fn redeem_data_request_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    data_request_token: &str,
+    kind: DataRequestKind,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+DELETE FROM data_request_tokens
+WHERE data_request_token = $1
+    AND kind = $2
+    AND requested_at > now() - interval '1 day'
+RETURNING subscriber_id
+"#,
+        data_request_token,
+        kind.as_str()
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.subscriber_id))
+}
+
+#[tracing::instrument(name = "Collect subscriber data", skip(transaction))]
+async fn get_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<SubscriberDataExport, sqlx::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+SELECT
+    id, email, email_original, name, subscribed_at, status, delivery_cadence, locale,
+    custom_attributes
+FROM subscriptions
+WHERE id = $1
+"#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let subscription_tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+    let list_memberships = sqlx::query_as!(
+        ListMembershipRecord,
+        r#"
+SELECT lists.slug AS list, list_memberships.status, list_memberships.created_at
+FROM list_memberships
+JOIN lists ON lists.id = list_memberships.list_id
+WHERE list_memberships.subscriber_id = $1
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let consent_events = sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+SELECT
+    consent_events.event_type, lists.slug AS "list?", consent_events.occurred_at,
+    consent_events.ip_address, consent_events.user_agent, consent_events.source,
+    consent_events.consent_text_version
+FROM consent_events
+LEFT JOIN lists ON lists.id = consent_events.list_id
+WHERE consent_events.subscriber_id = $1
+ORDER BY consent_events.occurred_at
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let preference_changes = sqlx::query_as!(
+        PreferenceChangeRecord,
+        r#"
+SELECT field, old_value, new_value, changed_at
+FROM preference_changes
+WHERE subscriber_id = $1
+ORDER BY changed_at
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+SELECT
+    issue_delivery_queue.newsletter_issue_id,
+    newsletter_issues.title,
+    issue_delivery_queue.status,
+    issue_delivery_queue.n_retries,
+    issue_delivery_queue.last_error,
+    issue_delivery_queue.updated_at
+FROM issue_delivery_queue
+JOIN newsletter_issues
+    ON newsletter_issues.newsletter_issue_id = issue_delivery_queue.newsletter_issue_id
+WHERE issue_delivery_queue.subscriber_id = $1
+ORDER BY issue_delivery_queue.updated_at
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let issues_received = sqlx::query_as!(
+        IssueRecipientRecord,
+        r#"
+SELECT newsletter_issue_id, tracking_token, sent_at
+FROM issue_recipients
+WHERE subscriber_id = $1
+ORDER BY sent_at
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let engagement_events = sqlx::query_as!(
+        EngagementEventRecord,
+        r#"
+SELECT
+    issue_recipients.newsletter_issue_id,
+    engagement_events.kind,
+    engagement_events.link_index,
+    engagement_events.occurred_at
+FROM engagement_events
+JOIN issue_recipients ON issue_recipients.tracking_token = engagement_events.tracking_token
+WHERE issue_recipients.subscriber_id = $1
+ORDER BY engagement_events.occurred_at
+"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let digests = sqlx::query_as!(
+        DigestRecord,
+        r#"SELECT id, n_issues, sent_at FROM digests WHERE subscriber_id = $1 ORDER BY sent_at"#,
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let onboarding = sqlx::query_as!(
+        OnboardingRecord,
+        r#"
+SELECT started_at, next_step, status, updated_at
+FROM onboarding_progress
+WHERE subscriber_id = $1
+"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(SubscriberDataExport {
+        subscriber,
+        subscription_tokens,
+        list_memberships,
+        consent_events,
+        preference_changes,
+        deliveries,
+        issues_received,
+        engagement_events,
+        digests,
+        onboarding,
+    })
+}
+
+#[tracing::instrument(name = "Erase subscriber", skip(transaction))]
+async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let email = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .email;
+    sqlx::query!(
+        r#"DELETE FROM data_request_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM preference_changes WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM email_change_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+DELETE FROM engagement_events
+WHERE tracking_token IN (SELECT tracking_token FROM issue_recipients WHERE subscriber_id = $1)
+"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM issue_recipients WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM digests WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM onboarding_progress WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM list_memberships WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM consent_events WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"
+INSERT INTO suppressed_emails (email_hash, suppressed_at)
+VALUES ($1, $2)
+ON CONFLICT DO NOTHING
+"#,
+        suppression_hash(&email),
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum DataRequestError {
+    ValidationError(String),
+    UnknownToken,
+    DatabaseError(sqlx::Error),
+    SendEmailError(reqwest::Error),
+}
+
+impl std::fmt::Display for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to process a data request.")
+    }
+}
+
+impl ResponseError for DataRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataRequestError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DataRequestError::UnknownToken => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<EmailError> for DataRequestError {
+    fn from(e: EmailError) -> Self {
+        Self::ValidationError(e.to_string())
+    }
+}
+
+impl From<sqlx::Error> for DataRequestError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<reqwest::Error> for DataRequestError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::SendEmailError(e)
+    }
+}
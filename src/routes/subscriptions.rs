@@ -1,41 +1,204 @@
-use actix_web::{web, HttpResponse, ResponseError};
-use chrono::Utc;
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use chrono::{Duration, Utc};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    bot_protection::{issue_form_token, verify_form_token, FormTokenError, SubscribeProtection},
+    deliverability::DeliverabilityCheck,
+    domain::{
+        ConsentEventType, ConsentMetadata, EmailPolicy, NameRules, NewSubscriber,
+        NewSubscriberError, SubscriberEmail,
+    },
     email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    i18n::{Locale, Localize},
+    rate_limiting::ClientIp,
+    routes::{is_suppressed, suppression_hash},
+    startup::{ApplicationBaseUrl, ConsentTextVersion},
+    utils::html_escape,
 };
 
+/// The list subscribers join when the form does not name one.
+pub const DEFAULT_LIST: &str = "newsletter";
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    source: Option<String>,
+    list: Option<String>,
+    /// Hidden from people: only bots fill it in.
+    #[serde(default)]
+    website: String,
+    form_token: Option<String>,
+    captcha_response: Option<String>,
+    /// Overrides the languages the browser asks for.
+    locale: Option<String>,
+}
+
+impl FormData {
+    /// Validate the name and email of the form, as the handler does.
+    pub fn new_subscriber(
+        self,
+        name_rules: &NameRules,
+    ) -> Result<NewSubscriber, NewSubscriberError> {
+        NewSubscriber::parse(self.name, self.email, name_rules)
+    }
+}
+
+/// The subscribe form, carrying a signed timestamp of when it was served and
+/// a honeypot field. Sites using a CAPTCHA add its widget, posting its
+/// response as `captcha_response`. It is worded in the language the browser
+/// asks for, which subscribers can change.
+#[tracing::instrument(name = "Render the subscribe form", skip(req, subscribe_protection))]
+pub async fn subscribe_form(
+    parameters: web::Query<SubscribeFormParameters>,
+    req: HttpRequest,
+    subscribe_protection: web::Data<SubscribeProtection>,
+) -> HttpResponse {
+    let form_token = issue_form_token(&subscribe_protection.settings.form_secret, Utc::now());
+    let list = parameters.0.list.unwrap_or_else(|| DEFAULT_LIST.into());
+    let locale = Locale::from_request(&req);
+    let locale_options: String = Locale::ALL
+        .iter()
+        .map(|option| {
+            format!(
+                r#"<option value="{}"{}>{}</option>"#,
+                option.as_str(),
+                if *option == locale { " selected" } else { "" },
+                option.message("locale-name", &[])
+            )
+        })
+        .collect();
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<form action="/subscriptions" method="post">
+<label>{name} <input type="text" name="name" required></label><br>
+<label>{email} <input type="email" name="email" required></label><br>
+<label>{language} <select name="locale">{locale_options}</select></label><br>
+<div style="display:none" aria-hidden="true">
+<label>Website <input type="text" name="website" tabindex="-1" autocomplete="off"></label>
+</div>
+<input type="hidden" name="list" value="{list}">
+<input type="hidden" name="form_token" value="{form_token}">
+<button type="submit">{button}</button>
+</form>
+</body>
+</html>"#,
+            lang = locale.as_str(),
+            title = locale.message("subscribe-title", &[]),
+            name = locale.message("subscribe-name", &[]),
+            email = locale.message("subscribe-email", &[]),
+            language = locale.message("subscribe-language", &[]),
+            locale_options = locale_options,
+            list = html_escape(&list),
+            form_token = form_token,
+            button = locale.message("subscribe-button", &[]),
+        ))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SubscribeFormParameters {
+    list: Option<String>,
 }
 
 #[tracing::instrument(
 name = "Adding a new subscriber",
-skip(_form,db_pool,email_client,base_url),
+skip(_form,req,db_pool,email_client,base_url,consent_text_version,subscribe_protection,email_policy,deliverability_check,name_rules),
 fields(
 subscriber_email = %_form.email,
 subscriber_name= %_form.name
 )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
     _form: web::Form<FormData>,
+    req: HttpRequest,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    consent_text_version: web::Data<ConsentTextVersion>,
+    subscribe_protection: web::Data<SubscribeProtection>,
+    email_policy: web::Data<EmailPolicy>,
+    deliverability_check: web::Data<DeliverabilityCheck>,
+    name_rules: web::Data<NameRules>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = _form.0.try_into()?;
+    let consent = consent_metadata(&req, _form.source.clone(), &consent_text_version.0);
+    let locale = subscriber_locale(&_form, &req)?;
+    // Bots caught out get the answer a person would, so that they do not
+    // learn how to get around our checks.
+    if !check_for_bots(
+        &_form,
+        consent.ip_address.as_deref(),
+        &subscribe_protection,
+        locale,
+    )
+    .await?
+    {
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let list = _form.list.clone().unwrap_or_else(|| DEFAULT_LIST.into());
+    let new_subscriber =
+        validate_new_subscriber(_form.0, &name_rules, &email_policy, &deliverability_check)
+            .await
+            .map_err(|e| SubscribeError::InvalidFields(e, locale))?;
+    if let Some(retry_after) = record_subscription_attempt(
+        &db_pool,
+        consent.ip_address.as_deref(),
+        &new_subscriber.email,
+        &subscribe_protection,
+    )
+    .await?
+    {
+        return Err(SubscribeError::TooManyAttempts(retry_after));
+    }
     let mut transaction = db_pool.begin().await?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber).await?;
+    // Addresses erased on request are not added back, but we answer as usual
+    // to avoid revealing who asked to be forgotten.
+    if is_suppressed(&mut transaction, &new_subscriber.email).await? {
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let list_id = get_list_id(&mut transaction, &list).await?.ok_or_else(|| {
+        SubscribeError::ValidationError(
+            locale.message("list-unknown", &[("list", list.as_str().into())]),
+        )
+    })?;
+    // Subscribers already known keep their locale: anybody can fill in the
+    // form with their address, only they can change it from their preferences.
+    let subscriber_id =
+        match get_subscriber_id_from_email(&mut transaction, &new_subscriber.email).await? {
+            Some(subscriber_id) => subscriber_id,
+            None => insert_subscriber(&mut transaction, &new_subscriber, locale).await?,
+        };
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        ConsentEventType::Requested,
+        &consent,
+    )
+    .await?;
+    if !request_list_membership(&mut transaction, subscriber_id, list_id).await? {
+        // Already a confirmed member of this list, there is nothing to confirm.
+        transaction.commit().await?;
+        return Ok(HttpResponse::Ok().finish());
+    }
     let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token).await?;
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &subscription_token,
+    )
+    .await?;
 
     transaction.commit().await?;
     send_confirmation_email(
@@ -43,11 +206,176 @@ pub async fn subscribe(
         new_subscriber,
         &base_url.0,
         &subscription_token,
+        locale,
     )
     .await?;
 
     Ok(HttpResponse::Ok().finish())
 }
+
+/// The locale picked on the form or, failing that, the one the browser asks
+/// for.
+fn subscriber_locale(form: &FormData, req: &HttpRequest) -> Result<Locale, SubscribeError> {
+    let requested = Locale::from_request(req);
+    match form.locale.as_deref().filter(|locale| !locale.is_empty()) {
+        Some(locale) => Locale::parse(locale).map_err(|_| {
+            SubscribeError::ValidationError(
+                requested.message("locale-unsupported", &[("locale", locale.into())]),
+            )
+        }),
+        None => Ok(requested),
+    }
+}
+
+/// Everything wrong with the name and email of the form.
+async fn validate_new_subscriber(
+    form: FormData,
+    name_rules: &NameRules,
+    email_policy: &EmailPolicy,
+    deliverability_check: &DeliverabilityCheck,
+) -> Result<NewSubscriber, NewSubscriberError> {
+    let new_subscriber = form.new_subscriber(name_rules)?;
+    email_policy.check(&new_subscriber.email)?;
+    deliverability_check.check(&new_subscriber.email).await?;
+    Ok(new_subscriber)
+}
+/// Returns `false` for submissions made by a bot.
+#[tracing::instrument(name = "Check the subscribe form for bots", skip_all)]
+async fn check_for_bots(
+    form: &FormData,
+    remote_ip: Option<&str>,
+    subscribe_protection: &SubscribeProtection,
+    locale: Locale,
+) -> Result<bool, SubscribeError> {
+    if !form.website.is_empty() {
+        tracing::warn!("The honeypot field of the subscribe form was filled in.");
+        return Ok(false);
+    }
+    let settings = &subscribe_protection.settings;
+    let form_token = form.form_token.as_deref().ok_or_else(|| {
+        SubscribeError::ValidationError(locale.message("form-token-missing", &[]))
+    })?;
+    match verify_form_token(
+        &settings.form_secret,
+        form_token,
+        Duration::seconds(settings.min_submit_seconds),
+        Duration::seconds(settings.max_form_age_seconds),
+        Utc::now(),
+    ) {
+        Ok(()) => {}
+        Err(FormTokenError::TooFast) => {
+            tracing::warn!("The subscribe form was submitted too fast.");
+            return Ok(false);
+        }
+        Err(FormTokenError::Invalid) | Err(FormTokenError::Expired) => {
+            return Err(SubscribeError::ValidationError(
+                locale.message("form-expired", &[]),
+            ))
+        }
+    }
+    if let Some(captcha_verifier) = &subscribe_protection.captcha_verifier {
+        let captcha_response = form
+            .captcha_response
+            .as_deref()
+            .filter(|r| !r.is_empty())
+            .ok_or_else(|| {
+                SubscribeError::ValidationError(locale.message("captcha-missing", &[]))
+            })?;
+        if !captcha_verifier
+            .verify(captcha_response, remote_ip)
+            .await
+            .map_err(SubscribeError::CaptchaError)?
+        {
+            return Err(SubscribeError::ValidationError(
+                locale.message("captcha-failed", &[]),
+            ));
+        }
+    }
+    Ok(true)
+}
+
+/// Record a submission of the subscribe form. Returns how many seconds the
+/// client has to wait if the IP address or the email address was submitted
+/// too often in the last hour.
+#[tracing::instrument(
+    name = "Record a subscription attempt",
+    skip(db_pool, email, subscribe_protection)
+)]
+async fn record_subscription_attempt(
+    db_pool: &PgPool,
+    ip_address: Option<&str>,
+    email: &SubscriberEmail,
+    subscribe_protection: &SubscribeProtection,
+) -> Result<Option<i64>, sqlx::Error> {
+    let now = Utc::now();
+    let window = Duration::hours(1);
+    let email_hash = suppression_hash(email.as_ref());
+    sqlx::query!(
+        r#"DELETE FROM subscription_attempts WHERE attempted_at < $1"#,
+        now - Duration::days(1)
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query!(
+        r#"
+INSERT INTO subscription_attempts (ip_address, email_hash, attempted_at)
+VALUES ($1, $2, $3)
+"#,
+        ip_address,
+        email_hash,
+        now
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let attempts = sqlx::query!(
+        r#"
+SELECT
+    COUNT(*) FILTER (WHERE ip_address = $1) AS "ip_attempts!",
+    MIN(attempted_at) FILTER (WHERE ip_address = $1) AS first_ip_attempt,
+    COUNT(*) FILTER (WHERE email_hash = $2) AS "email_attempts!",
+    MIN(attempted_at) FILTER (WHERE email_hash = $2) AS first_email_attempt
+FROM subscription_attempts
+WHERE attempted_at > $3 AND (ip_address = $1 OR email_hash = $2)
+"#,
+        ip_address,
+        email_hash,
+        now - window
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let settings = &subscribe_protection.settings;
+    // Attempts fall out of the window an hour after they were made.
+    let retry_after = [
+        (
+            attempts.ip_attempts > settings.max_attempts_per_ip,
+            attempts.first_ip_attempt,
+        ),
+        (
+            attempts.email_attempts > settings.max_attempts_per_email,
+            attempts.first_email_attempt,
+        ),
+    ]
+    .into_iter()
+    .filter(|(is_over_limit, _)| *is_over_limit)
+    .filter_map(|(_, first_attempt)| first_attempt)
+    .map(|first_attempt| (first_attempt + window - now).num_seconds().max(1))
+    .max();
+    Ok(retry_after)
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
@@ -55,12 +383,14 @@ pub async fn subscribe(
 async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1,$2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id) VALUES ($1,$2,$3)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction)
     .await
@@ -71,17 +401,66 @@ async fn store_token(
     Ok(())
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+#[tracing::instrument(name = "Get subscriber_id from email", skip(transaction, email))]
+pub async fn get_subscriber_id_from_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.id))
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber {
-            email: email,
-            name: name,
-        })
-    }
+#[tracing::instrument(name = "Get list_id from slug", skip(transaction))]
+pub async fn get_list_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(r#"SELECT id FROM lists WHERE slug = $1"#, slug)
+        .fetch_optional(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(result.map(|r| r.id))
+}
+
+/// Returns `false` if the subscriber already is a confirmed member of the list.
+#[tracing::instrument(name = "Request list membership", skip(transaction))]
+async fn request_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+VALUES ($1, $2, 'pending_confirmation', $3)
+ON CONFLICT (subscriber_id, list_id) DO UPDATE
+SET status = 'pending_confirmation'
+WHERE list_memberships.status <> 'confirmed'
+RETURNING status
+"#,
+        subscriber_id,
+        list_id,
+        Utc::now()
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.is_some())
 }
 
 #[tracing::instrument(
@@ -91,17 +470,20 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    locale: Locale,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-VALUES ($1, $2, $3, $4, 'pending_confirmation')
+INSERT INTO subscriptions (id, email, email_original, name, subscribed_at, status, locale)
+VALUES ($1, $2, $3, $4, $5, 'pending_confirmation', $6)
 "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.original(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        locale.as_str()
     )
     .execute(transaction)
     .await
@@ -112,6 +494,69 @@ VALUES ($1, $2, $3, $4, 'pending_confirmation')
     Ok(subscriber_id)
 }
 
+pub fn consent_metadata(
+    req: &HttpRequest,
+    source: Option<String>,
+    consent_text_version: &str,
+) -> ConsentMetadata {
+    // Resolved by the rate limiting middleware from the proxies we trust.
+    let ip_address = req
+        .extensions()
+        .get::<ClientIp>()
+        .map(|client_ip| client_ip.0)
+        .or_else(|| req.peer_addr().map(|addr| addr.ip()))
+        .map(|ip| ip.to_string());
+    let user_agent = req
+        .headers()
+        .get(actix_web::http::header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    ConsentMetadata {
+        ip_address,
+        user_agent,
+        source: source.filter(|s| !s.trim().is_empty()),
+        consent_text_version: consent_text_version.to_string(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Record a consent event",
+    skip(transaction, subscriber_id, list_id, consent)
+)]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    event_type: ConsentEventType,
+    consent: &ConsentMetadata,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO consent_events (
+    id, subscriber_id, list_id, event_type, occurred_at,
+    ip_address, user_agent, source, consent_text_version
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+"#,
+        Uuid::new_v4(),
+        subscriber_id,
+        list_id,
+        event_type.as_str(),
+        Utc::now(),
+        consent.ip_address,
+        consent.user_agent,
+        consent.source,
+        consent.consent_text_version
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, new_subscriber, base_url)
@@ -121,22 +566,27 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
+    locale: Locale,
 ) -> Result<(), reqwest::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
+    let html_body = locale.message(
+        "confirmation-email-body",
+        &[("link", confirmation_link.into())],
     );
     email_client
-        .send_email(new_subscriber.email, "Welcome!", "text/html", &html_body)
+        .send_email(
+            new_subscriber.email,
+            &locale.message("confirmation-email-subject", &[]),
+            "text/html",
+            &html_body,
+        )
         .await
 }
 
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -149,6 +599,12 @@ pub struct StoreTokenError(sqlx::Error);
 
 impl ResponseError for StoreTokenError {}
 
+impl std::error::Error for StoreTokenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
 impl std::fmt::Display for StoreTokenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -161,30 +617,74 @@ impl std::fmt::Display for StoreTokenError {
 #[derive(Debug)]
 pub enum SubscribeError {
     ValidationError(String),
+    /// With the locale to describe the errors in.
+    InvalidFields(NewSubscriberError, Locale),
     DatabaseError(sqlx::Error),
     StoreTokenError(StoreTokenError),
     SendEmailError(reqwest::Error),
+    /// Seconds until the client may try again.
+    TooManyAttempts(i64),
+    CaptchaError(reqwest::Error),
 }
 
 impl std::fmt::Display for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Failed to create a new subscriber.")
+        match self {
+            SubscribeError::ValidationError(e) => write!(f, "{}", e),
+            SubscribeError::InvalidFields(e, _) => write!(f, "{}", e),
+            SubscribeError::TooManyAttempts(_) => {
+                write!(f, "Too many attempts to subscribe, try again later.")
+            }
+            _ => write!(f, "Failed to create a new subscriber."),
+        }
     }
 }
 
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) | SubscribeError::InvalidFields(..) => {
+                StatusCode::BAD_REQUEST
+            }
+            SubscribeError::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            SubscribeError::TooManyAttempts(retry_after) => {
+                response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+            }
+            // Clients get what is wrong with each field, to point it out next
+            // to the field in their own words.
+            SubscribeError::InvalidFields(e, locale) => {
+                return response.json(serde_json::json!({
+                    "message": e.localize(*locale),
+                    "fields": {
+                        "name": e.name.as_ref().map(|e| field_error(e, *locale)),
+                        "email": e.email.as_ref().map(|e| field_error(e, *locale)),
+                    },
+                }));
+            }
+            SubscribeError::ValidationError(e) => {
+                return response.json(serde_json::json!({ "message": e }));
+            }
+            _ => {}
+        }
+        response.body(self.to_string())
+    }
 }
 
-impl From<String> for SubscribeError {
-    fn from(e: String) -> Self {
-        Self::ValidationError(e)
+/// The error as serialized, with its message in the subscriber's language
+/// added.
+fn field_error<E: serde::Serialize + Localize>(e: &E, locale: Locale) -> serde_json::Value {
+    let mut value = serde_json::to_value(e).expect("Field errors serialize to JSON.");
+    if let Some(fields) = value.as_object_mut() {
+        fields.insert("message".into(), e.localize(locale).into());
     }
+    value
 }
 
 impl From<sqlx::Error> for SubscribeError {
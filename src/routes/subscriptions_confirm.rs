@@ -1,43 +1,194 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::{
+    domain::ConsentEventType,
+    i18n::Locale,
+    onboarding::start_onboarding,
+    routes::{consent_metadata, record_consent_event},
+    startup::ConsentTextVersion,
+    utils::{message_page, wants_json},
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(_parameters, db_pool))]
+/// Confirmation links stop working after a week. Subscribing again sends a
+/// new one.
+const CONFIRMATION_LINK_LIFETIME_DAYS: i64 = 7;
+
+/// What became of a click on a confirmation link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Confirmed,
+    AlreadyConfirmed,
+    ExpiredToken,
+    InvalidToken,
+    ServerError,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Confirmed => "confirmed",
+            Outcome::AlreadyConfirmed => "already_confirmed",
+            Outcome::ExpiredToken => "expired_token",
+            Outcome::InvalidToken => "invalid_token",
+            Outcome::ServerError => "server_error",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Outcome::Confirmed | Outcome::AlreadyConfirmed => StatusCode::OK,
+            Outcome::ExpiredToken => StatusCode::GONE,
+            Outcome::InvalidToken => StatusCode::UNAUTHORIZED,
+            Outcome::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// A page for subscribers following the link from their email, JSON for
+    /// API clients asking for it.
+    fn response(&self, locale: Locale, json: bool) -> HttpResponse {
+        let message_id = self.as_str().replace('_', "-");
+        let title = locale.message(&format!("confirm-{}-title", message_id), &[]);
+        let message = locale.message(&format!("confirm-{}-message", message_id), &[]);
+        let mut response = HttpResponse::build(self.status_code());
+        if json {
+            return response.json(serde_json::json!({
+                "status": self.as_str(),
+                "message": message,
+            }));
+        }
+        response
+            .content_type("text/html; charset=utf-8")
+            .body(message_page(locale.as_str(), &title, &message))
+    }
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(_parameters, req, db_pool, consent_text_version)
+)]
 pub async fn confirm(
     _parameters: web::Query<Parameters>,
+    req: HttpRequest,
     db_pool: web::Data<PgPool>,
+    consent_text_version: web::Data<ConsentTextVersion>,
 ) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&db_pool, &_parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    let json = wants_json(&req);
+    match try_confirm(
+        &_parameters.subscription_token,
+        &req,
+        &db_pool,
+        &consent_text_version.0,
+    )
+    .await
+    {
+        // Pages are worded in the language of the subscriber, once we know
+        // who they are.
+        Ok((outcome, locale)) => {
+            outcome.response(locale.unwrap_or_else(|| Locale::from_request(&req)), json)
+        }
+        Err(e) => {
+            tracing::error!("Failed to confirm a subscriber: {:?}", e);
+            Outcome::ServerError.response(Locale::from_request(&req), json)
+        }
+    }
+}
+
+/// Returns the outcome, with the locale of the subscriber the token belongs
+/// to if there is one.
+async fn try_confirm(
+    subscription_token: &str,
+    req: &HttpRequest,
+    db_pool: &PgPool,
+    consent_text_version: &str,
+) -> Result<(Outcome, Option<Locale>), sqlx::Error> {
+    let token = match get_confirmation_token(db_pool, subscription_token).await? {
+        Some(token) => token,
+        None => return Ok((Outcome::InvalidToken, None)),
     };
+    let locale = Some(Locale::from_stored(&token.locale));
+    if token.membership_status == "confirmed" {
+        return Ok((Outcome::AlreadyConfirmed, locale));
+    }
+    if token.created_at < Utc::now() - Duration::days(CONFIRMATION_LINK_LIFETIME_DAYS) {
+        return Ok((Outcome::ExpiredToken, locale));
+    }
 
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&db_pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
-        }
+    let consent = consent_metadata(req, None, consent_text_version);
+    let mut transaction = db_pool.begin().await?;
+    let is_newly_confirmed =
+        confirm_subscriber(&mut transaction, token.subscriber_id, token.list_id).await?;
+    start_onboarding(&mut transaction, token.subscriber_id, Utc::now()).await?;
+    // A second click racing the first one is not a new consent.
+    if is_newly_confirmed {
+        record_consent_event(
+            &mut transaction,
+            token.subscriber_id,
+            token.list_id,
+            ConsentEventType::Confirmed,
+            &consent,
+        )
+        .await?;
     }
+    transaction.commit().await?;
+    Ok((Outcome::Confirmed, locale))
+}
+
+struct ConfirmationToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    created_at: chrono::DateTime<Utc>,
+    membership_status: String,
+    locale: String,
+}
+
+#[tracing::instrument(name = "Get a confirmation token", skip_all)]
+async fn get_confirmation_token(
+    db_pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+SELECT
+    subscription_tokens.subscriber_id,
+    subscription_tokens.list_id,
+    subscription_tokens.created_at,
+    list_memberships.status AS membership_status,
+    subscriptions.locale
+FROM subscription_tokens
+JOIN list_memberships ON list_memberships.subscriber_id = subscription_tokens.subscriber_id
+    AND list_memberships.list_id = subscription_tokens.list_id
+JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+WHERE subscription_tokens.subscription_token = $1
+"#,
+        subscription_token
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
 }
 
 #[tracing::instrument(
     name = "Get Subscriber_id from token",
     skip(db_pool, subscription_token)
 )]
-async fn get_subscriber_id_from_token(
+pub async fn get_subscriber_id_from_token(
     db_pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscriber_id, list_id FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token
     )
     .fetch_optional(db_pool)
@@ -47,20 +198,47 @@ async fn get_subscriber_id_from_token(
         e
     })?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.list_id)))
 }
 
-#[tracing::instrument(name = "Mark Subscriber as Confirm", skip(db_pool, subscriber_id))]
-async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+/// Returns whether the membership was still pending confirmation.
+#[tracing::instrument(
+    name = "Mark Subscriber as Confirm",
+    skip(transaction, subscriber_id, list_id)
+)]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+UPDATE subscriptions SET status = 'confirmed'
+WHERE id = $1 AND status = 'pending_confirmation'
+"#,
         subscriber_id
     )
-    .execute(db_pool)
+    .execute(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+    let is_newly_confirmed = sqlx::query!(
+        r#"
+UPDATE list_memberships SET status = 'confirmed'
+WHERE subscriber_id = $1 AND list_id = $2 AND status <> 'confirmed'
+"#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .rows_affected()
+        > 0;
+    Ok(is_newly_confirmed)
 }
@@ -0,0 +1,825 @@
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use chrono::Utc;
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    deliverability::DeliverabilityCheck,
+    domain::{
+        DeliveryCadence, EmailError, EmailPolicy, NameError, NameRules, SubscriberEmail,
+        SubscriberName,
+    },
+    email_client::EmailClient,
+    i18n::{Locale, Localize},
+    routes::{
+        generate_subscription_token, get_subscriber_id_from_email, get_subscriber_id_from_token,
+        is_suppressed,
+    },
+    startup::ApplicationBaseUrl,
+    utils::{html_escape, message_page, wants_json},
+};
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscription_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesUpdate {
+    subscription_token: String,
+    name: Option<String>,
+    email: Option<String>,
+    lists: Option<Vec<String>>,
+    delivery_cadence: Option<String>,
+    locale: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeParameters {
+    email_change_token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Preferences {
+    pub name: String,
+    pub email: String,
+    pub delivery_cadence: String,
+    pub locale: String,
+    pub lists: Vec<ListPreference>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListPreference {
+    pub slug: String,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+#[tracing::instrument(name = "Show subscriber preferences", skip(parameters, req, db_pool))]
+pub async fn preferences(
+    parameters: web::Query<PreferencesParameters>,
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = authenticate(&db_pool, &parameters.subscription_token, &req).await?;
+    let preferences = get_preferences(&db_pool, subscriber_id).await?;
+
+    if wants_json(&req) {
+        return Ok(HttpResponse::Ok().json(preferences));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(preferences_page(
+            &parameters.subscription_token,
+            &preferences,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(
+        body,
+        req,
+        db_pool,
+        email_client,
+        base_url,
+        email_policy,
+        name_rules,
+        deliverability_check
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_preferences(
+    body: web::Json<PreferencesUpdate>,
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_policy: web::Data<EmailPolicy>,
+    name_rules: web::Data<NameRules>,
+    deliverability_check: web::Data<DeliverabilityCheck>,
+) -> Result<HttpResponse, PreferencesError> {
+    let update = body.into_inner();
+    let subscriber_id = authenticate(&db_pool, &update.subscription_token, &req).await?;
+    let current = get_preferences(&db_pool, subscriber_id).await?;
+    // Errors are worded in the language the subscriber reads us in.
+    let current_locale = Locale::from_stored(&current.locale);
+    let invalid = |e| PreferencesError::InvalidPreference(e, current_locale);
+    // Validate everything up front, so that a request is applied entirely or not at all.
+    let name = update
+        .name
+        .map(|name| SubscriberName::parse_with_rules(name, &name_rules))
+        .transpose()
+        .map_err(|e| invalid(InvalidPreference::Name(e)))?;
+    let email = update
+        .email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(|e| invalid(InvalidPreference::Email(e)))?
+        // The stored address is normalized: only the case of its domain can
+        // change without making it another one.
+        .filter(|email| email.as_ref() != current.email);
+    if let Some(email) = &email {
+        email_policy
+            .check(email)
+            .map_err(|e| invalid(InvalidPreference::Email(e)))?;
+        deliverability_check
+            .check(email)
+            .await
+            .map_err(|e| invalid(InvalidPreference::Email(e)))?;
+    }
+    let delivery_cadence = update
+        .delivery_cadence
+        .map(|cadence| {
+            DeliveryCadence::parse(cadence.clone())
+                .map_err(|_| invalid(InvalidPreference::DeliveryCadence(cadence)))
+        })
+        .transpose()?;
+    let locale = update
+        .locale
+        .map(|locale| {
+            Locale::parse(&locale).map_err(|_| invalid(InvalidPreference::Locale(locale)))
+        })
+        .transpose()?;
+
+    let mut transaction = db_pool.begin().await?;
+    if let Some(name) = name {
+        update_name(&mut transaction, subscriber_id, &current.name, &name).await?;
+    }
+    if let Some(delivery_cadence) = delivery_cadence {
+        update_delivery_cadence(
+            &mut transaction,
+            subscriber_id,
+            &current.delivery_cadence,
+            delivery_cadence,
+        )
+        .await?;
+    }
+    if let Some(locale) = locale {
+        update_locale(&mut transaction, subscriber_id, &current.locale, locale).await?;
+    }
+    if let Some(lists) = update.lists {
+        update_lists(
+            &mut transaction,
+            subscriber_id,
+            &current.lists,
+            &lists,
+            current_locale,
+        )
+        .await?;
+    }
+    let email_change = match email {
+        Some(email) => {
+            let email_change_token =
+                request_email_change(&mut transaction, subscriber_id, &email, current_locale)
+                    .await?;
+            Some((email, email_change_token))
+        }
+        None => None,
+    };
+    transaction.commit().await?;
+
+    if let Some((email, email_change_token)) = email_change {
+        // The new address gets it in the language the subscriber just chose.
+        send_email_change_confirmation(
+            &email_client,
+            email,
+            &base_url.0,
+            &email_change_token,
+            locale.unwrap_or(current_locale),
+        )
+        .await?;
+    }
+
+    let preferences = get_preferences(&db_pool, subscriber_id).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+#[tracing::instrument(name = "Confirm an email change", skip(parameters, req, db_pool))]
+pub async fn confirm_email_change(
+    parameters: web::Query<EmailChangeParameters>,
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (status, locale, title, message) =
+        match try_confirm_email_change(&parameters.email_change_token, &req, &db_pool).await {
+            Ok((new_email, locale)) => (
+                StatusCode::OK,
+                locale,
+                "email-change-confirmed-title",
+                locale.message(
+                    "email-change-confirmed-message",
+                    &[("email", html_escape(new_email.as_ref()).into())],
+                ),
+            ),
+            Err(e) => {
+                let (status, locale) = match &e {
+                    PreferencesError::InvalidPreference(_, locale)
+                    | PreferencesError::UnknownToken(locale) => (e.status_code(), *locale),
+                    _ => {
+                        tracing::error!("Failed to confirm an email change: {:?}", e);
+                        (e.status_code(), Locale::from_request(&req))
+                    }
+                };
+                (
+                    status,
+                    locale,
+                    "email-change-failed-title",
+                    html_escape(&e.localize(locale)),
+                )
+            }
+        };
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(message_page(
+            locale.as_str(),
+            &locale.message(title, &[]),
+            &message,
+        ))
+}
+
+/// Returns the new address, with the locale of the subscriber it belongs to.
+async fn try_confirm_email_change(
+    email_change_token: &str,
+    req: &HttpRequest,
+    db_pool: &PgPool,
+) -> Result<(SubscriberEmail, Locale), PreferencesError> {
+    let mut transaction = db_pool.begin().await?;
+    let (subscriber_id, new_email) =
+        redeem_email_change_token(&mut transaction, email_change_token)
+            .await?
+            .ok_or_else(|| PreferencesError::UnknownToken(Locale::from_request(req)))?;
+    let subscriber = sqlx::query!(
+        r#"SELECT email, locale FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let locale = Locale::from_stored(&subscriber.locale);
+    let new_email = SubscriberEmail::parse(new_email)
+        .map_err(|e| PreferencesError::InvalidPreference(InvalidPreference::Email(e), locale))?;
+    // The address might have been taken since the change was requested.
+    if get_subscriber_id_from_email(&mut transaction, &new_email)
+        .await?
+        .is_some()
+    {
+        return Err(PreferencesError::InvalidPreference(
+            InvalidPreference::EmailTaken(new_email.original().to_string()),
+            locale,
+        ));
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET email = $2, email_original = $3 WHERE id = $1"#,
+        subscriber_id,
+        new_email.as_ref(),
+        new_email.original()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_preference_change(
+        &mut transaction,
+        subscriber_id,
+        "email",
+        Some(&subscriber.email),
+        Some(new_email.as_ref()),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok((new_email, locale))
+}
+
+async fn authenticate(
+    db_pool: &PgPool,
+    subscription_token: &str,
+    req: &HttpRequest,
+) -> Result<Uuid, PreferencesError> {
+    get_subscriber_id_from_token(db_pool, subscription_token)
+        .await?
+        .map(|(subscriber_id, _)| subscriber_id)
+        .ok_or_else(|| PreferencesError::UnknownToken(Locale::from_request(req)))
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip(db_pool))]
+async fn get_preferences(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Preferences, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT name, email, delivery_cadence, locale FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let lists = sqlx::query_as!(
+        ListPreference,
+        r#"
+SELECT lists.slug, lists.name, COALESCE(list_memberships.status = 'confirmed', false) AS "subscribed!"
+FROM lists
+LEFT JOIN list_memberships ON list_memberships.list_id = lists.id
+    AND list_memberships.subscriber_id = $1
+ORDER BY lists.name
+"#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(Preferences {
+        name: subscriber.name,
+        email: subscriber.email,
+        delivery_cadence: subscriber.delivery_cadence,
+        locale: subscriber.locale,
+        lists,
+    })
+}
+
+async fn update_name(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    current: &str,
+    name: &SubscriberName,
+) -> Result<(), sqlx::Error> {
+    if name.as_ref() == current {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_preference_change(
+        transaction,
+        subscriber_id,
+        "name",
+        Some(current),
+        Some(name.as_ref()),
+    )
+    .await
+}
+
+async fn update_delivery_cadence(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    current: &str,
+    delivery_cadence: DeliveryCadence,
+) -> Result<(), sqlx::Error> {
+    if delivery_cadence.as_str() == current {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET delivery_cadence = $2 WHERE id = $1"#,
+        subscriber_id,
+        delivery_cadence.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_preference_change(
+        transaction,
+        subscriber_id,
+        "delivery_cadence",
+        Some(current),
+        Some(delivery_cadence.as_str()),
+    )
+    .await
+}
+
+async fn update_locale(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    current: &str,
+    locale: Locale,
+) -> Result<(), sqlx::Error> {
+    if locale.as_str() == current {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET locale = $2 WHERE id = $1"#,
+        subscriber_id,
+        locale.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_preference_change(
+        transaction,
+        subscriber_id,
+        "locale",
+        Some(current),
+        Some(locale.as_str()),
+    )
+    .await
+}
+
+/// Joins the lists in `wanted` and leaves every other list. The subscriber
+/// already proved they own the address, so joining needs no confirmation.
+async fn update_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    current: &[ListPreference],
+    wanted: &[String],
+    locale: Locale,
+) -> Result<(), PreferencesError> {
+    if let Some(unknown) = wanted
+        .iter()
+        .find(|slug| !current.iter().any(|list| &list.slug == *slug))
+    {
+        return Err(PreferencesError::InvalidPreference(
+            InvalidPreference::UnknownList(unknown.clone()),
+            locale,
+        ));
+    }
+    for list in current {
+        let subscribe = wanted.contains(&list.slug);
+        if subscribe == list.subscribed {
+            continue;
+        }
+        let status = if subscribe {
+            "confirmed"
+        } else {
+            "unsubscribed"
+        };
+        let list_id = sqlx::query!(
+            r#"
+INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+SELECT $1, lists.id, $3, $4 FROM lists WHERE lists.slug = $2
+ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = $3
+RETURNING list_id
+"#,
+            subscriber_id,
+            list.slug,
+            status,
+            Utc::now()
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .list_id;
+        if subscribe {
+            // Every membership needs a token of its own to unsubscribe with.
+            sqlx::query!(
+                r#"
+INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+SELECT $1, $2, $3
+WHERE NOT EXISTS (
+    SELECT 1 FROM subscription_tokens WHERE subscriber_id = $2 AND list_id = $3
+)
+"#,
+                generate_subscription_token(),
+                subscriber_id,
+                list_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        record_preference_change(
+            transaction,
+            subscriber_id,
+            &format!("list:{}", list.slug),
+            Some(if list.subscribed {
+                "confirmed"
+            } else {
+                "unsubscribed"
+            }),
+            Some(status),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn request_email_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+    locale: Locale,
+) -> Result<String, PreferencesError> {
+    if is_suppressed(transaction, email).await?
+        || get_subscriber_id_from_email(transaction, email)
+            .await?
+            .is_some()
+    {
+        return Err(PreferencesError::InvalidPreference(
+            InvalidPreference::EmailUnavailable(email.original().to_string()),
+            locale,
+        ));
+    }
+    let email_change_token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+INSERT INTO email_change_tokens (email_change_token, subscriber_id, new_email, requested_at)
+VALUES ($1, $2, $3, $4)
+"#,
+        email_change_token,
+        subscriber_id,
+        email.original(),
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(email_change_token)
+}
+
+/// Email change tokens are single-use and expire after a day.
+async fn redeem_email_change_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_change_token: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+DELETE FROM email_change_tokens
+WHERE email_change_token = $1
+    AND requested_at > now() - interval '1 day'
+RETURNING subscriber_id, new_email
+"#,
+        email_change_token
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| (r.subscriber_id, r.new_email)))
+}
+
+#[tracing::instrument(
+    name = "Record a preference change",
+    skip(transaction, old_value, new_value)
+)]
+async fn record_preference_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO preference_changes (id, subscriber_id, field, old_value, new_value, changed_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+"#,
+        Uuid::new_v4(),
+        subscriber_id,
+        field,
+        old_value,
+        new_value,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an email change confirmation",
+    skip(email_client, email, base_url, email_change_token)
+)]
+async fn send_email_change_confirmation(
+    email_client: &EmailClient,
+    email: SubscriberEmail,
+    base_url: &str,
+    email_change_token: &str,
+    locale: Locale,
+) -> Result<(), reqwest::Error> {
+    let confirmation_link = format!(
+        "{}/subscriptions/preferences/confirm_email?email_change_token={}",
+        base_url, email_change_token
+    );
+    let html_body = locale.message(
+        "email-change-email-body",
+        &[("link", confirmation_link.into())],
+    );
+    email_client
+        .send_email(
+            email,
+            &locale.message("email-change-email-subject", &[]),
+            "text/html",
+            &html_body,
+        )
+        .await
+}
+
+fn preferences_page(subscription_token: &str, preferences: &Preferences) -> String {
+    let locale = Locale::from_stored(&preferences.locale);
+    let cadence_options: String = [
+        DeliveryCadence::Immediate,
+        DeliveryCadence::Daily,
+        DeliveryCadence::Weekly,
+    ]
+    .iter()
+    .map(|cadence| {
+        format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            cadence.as_str(),
+            if cadence.as_str() == preferences.delivery_cadence {
+                " selected"
+            } else {
+                ""
+            },
+            locale.message(&format!("preferences-cadence-{}", cadence.as_str()), &[])
+        )
+    })
+    .collect();
+    let locale_options: String = Locale::ALL
+        .iter()
+        .map(|locale| {
+            format!(
+                r#"<option value="{}"{}>{}</option>"#,
+                locale.as_str(),
+                if locale.as_str() == preferences.locale {
+                    " selected"
+                } else {
+                    ""
+                },
+                locale.message("locale-name", &[])
+            )
+        })
+        .collect();
+    let list_checkboxes: String = preferences
+        .lists
+        .iter()
+        .map(|list| {
+            format!(
+                r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+                html_escape(&list.slug),
+                if list.subscribed { " checked" } else { "" },
+                html_escape(&list.name)
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<form id="preferences">
+<input type="hidden" name="subscription_token" value="{token}">
+<label>{name_label} <input type="text" name="name" value="{name}"></label><br>
+<label>{email_label} <input type="email" name="email" value="{email}"></label><br>
+<label>{cadence_label} <select name="delivery_cadence">{cadence_options}</select></label><br>
+<label>{locale_label} <select name="locale">{locale_options}</select></label><br>
+<fieldset><legend>{lists_label}</legend>{list_checkboxes}</fieldset>
+<button type="submit">{save}</button>
+</form>
+<p id="status"></p>
+<script>
+document.getElementById("preferences").addEventListener("submit", async (event) => {{
+    event.preventDefault();
+    const form = new FormData(event.target);
+    const response = await fetch("/subscriptions/preferences", {{
+        method: "POST",
+        headers: {{ "Content-Type": "application/json" }},
+        body: JSON.stringify({{
+            subscription_token: form.get("subscription_token"),
+            name: form.get("name"),
+            email: form.get("email"),
+            delivery_cadence: form.get("delivery_cadence"),
+            locale: form.get("locale"),
+            lists: form.getAll("lists"),
+        }}),
+    }});
+    document.getElementById("status").textContent = response.ok
+        ? {saved}
+        : {not_saved};
+}});
+</script>
+</body>
+</html>"#,
+        lang = locale.as_str(),
+        title = locale.message("preferences-title", &[]),
+        name_label = locale.message("preferences-name", &[]),
+        email_label = locale.message("preferences-email", &[]),
+        cadence_label = locale.message("preferences-cadence", &[]),
+        locale_label = locale.message("preferences-language", &[]),
+        lists_label = locale.message("preferences-lists", &[]),
+        save = locale.message("preferences-save", &[]),
+        // String literals for the script.
+        saved = serde_json::Value::from(locale.message("preferences-saved", &[])),
+        not_saved = serde_json::Value::from(locale.message("preferences-not-saved", &[])),
+        token = html_escape(subscription_token),
+        name = html_escape(&preferences.name),
+        email = html_escape(&preferences.email),
+        cadence_options = cadence_options,
+        locale_options = locale_options,
+        list_checkboxes = list_checkboxes,
+    )
+}
+
+/// What is wrong with a preference a subscriber asked for.
+#[derive(Debug)]
+pub enum InvalidPreference {
+    Name(NameError),
+    Email(EmailError),
+    DeliveryCadence(String),
+    Locale(String),
+    UnknownList(String),
+    /// Suppressed or another subscriber's, which we do not tell apart.
+    EmailUnavailable(String),
+    /// Taken by another subscriber since the change was requested.
+    EmailTaken(String),
+}
+
+impl Localize for InvalidPreference {
+    fn localize(&self, locale: Locale) -> String {
+        match self {
+            InvalidPreference::Name(e) => e.localize(locale),
+            InvalidPreference::Email(e) => e.localize(locale),
+            InvalidPreference::DeliveryCadence(cadence) => locale.message(
+                "preferences-cadence-unsupported",
+                &[("cadence", cadence.as_str().into())],
+            ),
+            InvalidPreference::Locale(requested) => locale.message(
+                "locale-unsupported",
+                &[("locale", requested.as_str().into())],
+            ),
+            InvalidPreference::UnknownList(list) => {
+                locale.message("list-unknown", &[("list", list.as_str().into())])
+            }
+            InvalidPreference::EmailUnavailable(email) => locale.message(
+                "preferences-email-unavailable",
+                &[("email", email.as_str().into())],
+            ),
+            InvalidPreference::EmailTaken(email) => locale.message(
+                "preferences-email-taken",
+                &[("email", email.as_str().into())],
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PreferencesError {
+    /// With the locale to describe it in.
+    InvalidPreference(InvalidPreference, Locale),
+    /// With the locale to describe it in.
+    UnknownToken(Locale),
+    DatabaseError(sqlx::Error),
+    SendEmailError(reqwest::Error),
+}
+
+impl Localize for PreferencesError {
+    fn localize(&self, locale: Locale) -> String {
+        match self {
+            PreferencesError::InvalidPreference(e, _) => e.localize(locale),
+            PreferencesError::UnknownToken(_) => locale.message("preferences-unknown-token", &[]),
+            _ => locale.message("preferences-server-error", &[]),
+        }
+    }
+}
+
+impl std::fmt::Display for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreferencesError::InvalidPreference(e, _) => {
+                f.write_str(&e.localize(Locale::default()))
+            }
+            _ => write!(f, "Failed to update the subscriber preferences."),
+        }
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreferencesError::InvalidPreference(..) => StatusCode::BAD_REQUEST,
+            PreferencesError::UnknownToken(_) => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let locale = match self {
+            PreferencesError::InvalidPreference(_, locale)
+            | PreferencesError::UnknownToken(locale) => *locale,
+            _ => Locale::default(),
+        };
+        HttpResponse::build(self.status_code())
+            .json(serde_json::json!({ "message": self.localize(locale) }))
+    }
+}
+
+impl From<sqlx::Error> for PreferencesError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<reqwest::Error> for PreferencesError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::SendEmailError(e)
+    }
+}
@@ -0,0 +1,131 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    i18n::Locale,
+    routes::get_subscriber_id_from_token,
+    utils::{html_escape, message_page},
+};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscription_token: String,
+}
+
+/// Unsubscribe links only render a confirmation form, so that mail scanners
+/// prefetching links cannot unsubscribe anybody on their own.
+#[tracing::instrument(name = "Render the unsubscribe form", skip(parameters, req, db_pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    // Tokens we do not know get the form too: submitting it tells them so.
+    let locale = get_locale_from_token(&db_pool, &parameters.subscription_token)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| Locale::from_request(&req));
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{}">
+<head><meta charset="utf-8"><title>{}</title></head>
+<body>
+<form action="/subscriptions/unsubscribe" method="post">
+<input type="hidden" name="subscription_token" value="{}">
+<button type="submit">{}</button>
+</form>
+</body>
+</html>"#,
+            locale.as_str(),
+            locale.message("unsubscribe-title", &[]),
+            html_escape(&parameters.subscription_token),
+            locale.message("unsubscribe-button", &[])
+        ))
+}
+
+#[tracing::instrument(name = "Unsubscribe from a list", skip(form, db_pool))]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let id = match get_subscriber_id_from_token(&db_pool, &form.subscription_token).await {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match id {
+        None => HttpResponse::Unauthorized().finish(),
+        Some((subscriber_id, list_id)) => {
+            let (list_name, locale) = match leave_list(&db_pool, subscriber_id, list_id).await {
+                Ok(left) => left,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(message_page(
+                    locale.as_str(),
+                    &locale.message("unsubscribed-title", &[]),
+                    &locale.message(
+                        "unsubscribed-message",
+                        &[("list", html_escape(&list_name).into())],
+                    ),
+                ))
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get the locale of a subscriber from a token", skip_all)]
+async fn get_locale_from_token(
+    db_pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<Locale>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+SELECT subscriptions.locale
+FROM subscription_tokens
+JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+WHERE subscription_tokens.subscription_token = $1
+"#,
+        subscription_token
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| Locale::from_stored(&r.locale)))
+}
+
+/// Returns the name of the list and the locale of the subscriber.
+#[tracing::instrument(name = "Mark list membership as unsubscribed", skip(db_pool))]
+async fn leave_list(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(String, Locale), sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+UPDATE list_memberships SET status = 'unsubscribed'
+FROM lists, subscriptions
+WHERE list_memberships.subscriber_id = $1
+    AND list_memberships.list_id = $2
+    AND lists.id = list_memberships.list_id
+    AND subscriptions.id = list_memberships.subscriber_id
+RETURNING lists.name, subscriptions.locale
+"#,
+        subscriber_id,
+        list_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok((result.name, Locale::from_stored(&result.locale)))
+}
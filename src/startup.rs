@@ -1,12 +1,29 @@
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{dev::Server, middleware::from_fn, web, App, HttpServer};
+use secrecy::Secret;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
+    authentication::AdminToken,
+    bot_protection::{CaptchaVerifier, SubscribeProtection},
     configuration::{DatabaseSettings, Settings},
+    deliverability::{DeliverabilityCheck, DnsResolver, DomainResolver},
+    domain::{EmailPolicy, NameRules},
     email_client::EmailClient,
-    routes::{confirm, health_check, publish_newsletter, subscribe},
+    rate_limiting::{rate_limit, RateLimiter},
+    routes::{
+        ab_test_report, cancel_newsletter, configure_ab_test, confirm, confirm_email_change,
+        create_draft, delivery_status, delivery_status_stream, edit_draft, engagement_report,
+        erase_subscriber_data, erasure_form, export_subscriber_data, get_newsletter, health_check,
+        list_drafts, list_scheduled_newsletters, newsletter_archive, newsletter_feed,
+        newsletter_web_view, pause_newsletter, preferences, preview_newsletter, publish_draft,
+        publish_newsletter, request_data_erasure, request_data_export, reschedule_newsletter,
+        resume_newsletter, send_test_newsletter, subscribe, subscribe_form, track_click,
+        track_open, unsubscribe, unsubscribe_form, update_preferences,
+        update_subscriber_attributes,
+    },
 };
 
 pub struct Application {
@@ -16,27 +33,63 @@ pub struct Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct ConsentTextVersion(pub String);
+
+/// Third-party services subscriptions are checked against, which tests
+/// replace with local fakes.
+#[derive(Default)]
+pub struct ExternalChecks {
+    pub captcha_verifier: Option<Arc<dyn CaptchaVerifier>>,
+    pub domain_resolver: Option<Arc<dyn DomainResolver>>,
+}
+
+impl ExternalChecks {
+    pub fn from_configuration(configuration: &Settings) -> Result<Self, std::io::Error> {
+        let captcha_verifier = configuration
+            .subscribe_protection
+            .captcha
+            .clone()
+            .map(|captcha| Arc::new(captcha.verifier()) as Arc<dyn CaptchaVerifier>);
+        let domain_resolver = if configuration.deliverability.check_dns {
+            let resolver = DnsResolver::from_system_conf().map_err(std::io::Error::other)?;
+            Some(Arc::new(resolver) as Arc<dyn DomainResolver>)
+        } else {
+            None
+        };
+        Ok(Self {
+            captcha_verifier,
+            domain_resolver,
+        })
+    }
+}
+
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        let external_checks = ExternalChecks::from_configuration(&configuration)?;
+        Self::build_with_external_checks(configuration, external_checks).await
+    }
+
+    /// Build the application with external checks of our choosing rather
+    /// than the configured ones.
+    pub async fn build_with_external_checks(
+        configuration: Settings,
+        external_checks: ExternalChecks,
+    ) -> Result<Self, std::io::Error> {
+        configuration.validate().map_err(std::io::Error::other)?;
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.bear_token,
-            timeout,
-        );
+            .client()
+            .map_err(std::io::Error::other)?;
 
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
 
+        let rate_limiter = RateLimiter::new(configuration.rate_limit, connection_pool.clone());
+
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
         let server = run(
@@ -44,6 +97,20 @@ impl Application {
             connection_pool,
             email_client,
             configuration.application.base_url,
+            configuration.application.consent_text_version,
+            configuration.application.admin_token,
+            SubscribeProtection {
+                settings: configuration.subscribe_protection,
+                captcha_verifier: external_checks.captcha_verifier,
+            },
+            rate_limiter,
+            configuration.email_policy.policy(),
+            DeliverabilityCheck {
+                suggest_typos: configuration.deliverability.suggest_typos,
+                resolver: external_checks.domain_resolver,
+                timeout: configuration.deliverability.timeout(),
+            },
+            configuration.subscriber_name.rules(),
         )?;
 
         Ok(Self { port, server })
@@ -64,25 +131,151 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
+    consent_text_version: String,
+    admin_token: Secret<String>,
+    subscribe_protection: SubscribeProtection,
+    rate_limiter: RateLimiter,
+    email_policy: EmailPolicy,
+    deliverability_check: DeliverabilityCheck,
+    name_rules: NameRules,
 ) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let consent_text_version = web::Data::new(ConsentTextVersion(consent_text_version));
+    let admin_token = web::Data::new(AdminToken(admin_token));
+    let subscribe_protection = web::Data::new(subscribe_protection);
+    let rate_limiter = web::Data::new(rate_limiter);
+    let email_policy = web::Data::new(email_policy);
+    let deliverability_check = web::Data::new(deliverability_check);
+    let name_rules = web::Data::new(name_rules);
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(rate_limit))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .route("/subscriptions", web::get().to(subscribe_form))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/preferences", web::get().to(preferences))
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/confirm_email",
+                web::get().to(confirm_email_change),
+            )
+            .route(
+                "/subscriptions/data_export",
+                web::post().to(request_data_export),
+            )
+            .route(
+                "/subscriptions/data_export",
+                web::get().to(export_subscriber_data),
+            )
+            .route(
+                "/subscriptions/erasure",
+                web::post().to(request_data_erasure),
+            )
+            .route("/subscriptions/erasure", web::get().to(erasure_form))
+            .route(
+                "/subscriptions/erasure/confirm",
+                web::post().to(erase_subscriber_data),
+            )
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/t/o/{tracking_token}", web::get().to(track_open))
+            .route("/t/c/{click_id}", web::get().to(track_click))
+            .route("/newsletters", web::get().to(newsletter_archive))
+            .route("/newsletters/feed.atom", web::get().to(newsletter_feed))
+            .route("/newsletters/{slug}", web::get().to(newsletter_web_view))
+            .route("/admin/newsletters", web::post().to(create_draft))
+            .route("/admin/newsletters/drafts", web::get().to(list_drafts))
+            .route(
+                "/admin/newsletters/scheduled",
+                web::get().to(list_scheduled_newsletters),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}",
+                web::get().to(get_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}",
+                web::put().to(edit_draft),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/status",
+                web::get().to(delivery_status),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/status/stream",
+                web::get().to(delivery_status_stream),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/ab_test",
+                web::get().to(ab_test_report),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/ab_test",
+                web::put().to(configure_ab_test),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/engagement",
+                web::get().to(engagement_report),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/preview",
+                web::get().to(preview_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/test",
+                web::post().to(send_test_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/publish",
+                web::post().to(publish_draft),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/reschedule",
+                web::post().to(reschedule_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/cancel",
+                web::post().to(cancel_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/pause",
+                web::post().to(pause_newsletter),
+            )
+            .route(
+                "/admin/newsletters/{newsletter_issue_id}/resume",
+                web::post().to(resume_newsletter),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/attributes",
+                web::put().to(update_subscriber_attributes),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(consent_text_version.clone())
+            .app_data(admin_token.clone())
+            .app_data(subscribe_protection.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(email_policy.clone())
+            .app_data(deliverability_check.clone())
+            .app_data(name_rules.clone())
     })
     .listen(listener)?
     .run();
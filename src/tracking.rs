@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+/// A transparent 1x1 GIF, served by the open tracking endpoint.
+pub const TRACKING_PIXEL: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff\
+!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+/// The links of an issue that clicks are tracked for, in order of appearance.
+/// A link's position in this list is the index used in its click URL.
+pub fn tracked_links(content: &str) -> Vec<String> {
+    link_spans(content)
+        .into_iter()
+        .map(|span| content[span].replace("&amp;", "&"))
+        .collect()
+}
+
+/// Route the links of `content` through the click tracking endpoint and
+/// embed the open tracking pixel. Tracking tokens are random, so the URLs do
+/// not give away who the recipient is.
+pub fn add_tracking(content: &str, base_url: &str, tracking_token: &str) -> String {
+    let mut tracked = String::with_capacity(content.len());
+    let mut last = 0;
+    for (link_index, span) in link_spans(content).into_iter().enumerate() {
+        tracked.push_str(&content[last..span.start]);
+        tracked.push_str(&format!(
+            "{}/t/c/{}.{}",
+            base_url, tracking_token, link_index
+        ));
+        last = span.end;
+    }
+    tracked.push_str(&content[last..]);
+    tracked.push_str(&format!(
+        "<img src=\"{}/t/o/{}\" width=\"1\" height=\"1\" alt=\"\" />",
+        base_url, tracking_token
+    ));
+    tracked
+}
+
+/// Split a click id, `{tracking_token}.{link_index}`, into its parts.
+pub fn parse_click_id(click_id: &str) -> Option<(&str, i32)> {
+    let (tracking_token, link_index) = click_id.rsplit_once('.')?;
+    Some((tracking_token, link_index.parse().ok()?))
+}
+
+/// Where the http(s) URLs of the `href` attributes in `content` are.
+fn link_spans(content: &str) -> Vec<Range<usize>> {
+    const ATTRIBUTE: &str = "href=\"";
+    let mut spans = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = content[position..].find(ATTRIBUTE) {
+        let start = position + offset + ATTRIBUTE.len();
+        let end = match content[start..].find('"') {
+            Some(length) => start + length,
+            None => break,
+        };
+        let url = &content[start..end];
+        if url.starts_with("http://") || url.starts_with("https://") {
+            spans.push(start..end);
+        }
+        position = end;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tracking, parse_click_id, tracked_links};
+
+    const CONTENT: &str = r#"<p><a href="https://example.com/a?x=1&amp;y=2">A</a>
+<a href="mailto:editor@example.com">Mail us</a>
+<a href="http://example.com/b">B</a></p>"#;
+
+    #[test]
+    fn only_web_links_are_tracked() {
+        assert_eq!(
+            tracked_links(CONTENT),
+            vec!["https://example.com/a?x=1&y=2", "http://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn tracked_links_are_rewritten_in_order() {
+        let tracked = add_tracking(CONTENT, "https://app.test", "token");
+
+        assert!(tracked.contains(r#"<a href="https://app.test/t/c/token.0">A</a>"#));
+        assert!(tracked.contains(r#"<a href="mailto:editor@example.com">"#));
+        assert!(tracked.contains(r#"<a href="https://app.test/t/c/token.1">B</a>"#));
+        assert!(tracked
+            .ends_with(r#"<img src="https://app.test/t/o/token" width="1" height="1" alt="" />"#));
+    }
+
+    #[test]
+    fn click_ids_are_split_into_token_and_link_index() {
+        assert_eq!(parse_click_id("token.3"), Some(("token", 3)));
+        assert_eq!(parse_click_id("token"), None);
+        assert_eq!(parse_click_id("token.three"), None);
+    }
+}
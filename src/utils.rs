@@ -0,0 +1,32 @@
+use actix_web::{http::header, HttpRequest};
+
+/// Escape a value before interpolating it into an HTML page or email.
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A page showing a single message, in the language tagged `lang`. Both the
+/// title and the message are HTML.
+pub fn message_page(lang: &str, title: &str, message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head><meta charset="utf-8"><title>{}</title></head>
+<body><p>{}</p></body>
+</html>"#,
+        lang, title, message
+    )
+}
+
+/// Whether the client asked for JSON rather than a page meant for a browser.
+pub fn wants_json(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false)
+}
@@ -1,11 +1,18 @@
+use chrono::{Duration, Utc};
 use once_cell::sync::Lazy;
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod_practice::{
+    bot_protection::issue_form_token,
     configuration::{get_configuration, DatabaseSettings},
-    startup::{get_connection_pool, Application},
+    digest_delivery::send_due_digests,
+    email_client::EmailClient,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    newsletter_scheduler::{decide_due_ab_tests, release_due_issues},
+    onboarding::{send_due_onboarding_emails, OnboardingSequence},
+    startup::{get_connection_pool, Application, ExternalChecks},
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -14,21 +21,155 @@ pub struct TestApp {
     pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub admin_token: Secret<String>,
+    pub form_secret: Secret<String>,
+    pub onboarding: OnboardingSequence,
 }
 
 impl TestApp {
+    /// A token for a subscribe form served long enough ago to pass as
+    /// filled in by a person.
+    pub fn form_token(&self) -> String {
+        issue_form_token(&self.form_secret, Utc::now() - Duration::seconds(60))
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("{}&form_token={}", body, self.form_token()))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_form(&self, path: &str, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}{}", &self.address, path))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .bearer_auth(self.admin_token.expose_secret())
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin(&self, path: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}{}", &self.address, path))
+            .bearer_auth(self.admin_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin(&self, path: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}{}", &self.address, path))
+            .bearer_auth(self.admin_token.expose_secret())
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin(&self, path: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!("{}{}", &self.address, path))
+            .bearer_auth(self.admin_token.expose_secret())
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Run the delivery worker until the queue is drained.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, &self.email_client, &self.base_url)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Run one pass of the newsletter scheduler.
+    pub async fn release_due_issues(&self) -> usize {
+        release_due_issues(&self.db_pool).await.unwrap()
+    }
+
+    /// Run one pass of the digest job, as if it were `now`.
+    pub async fn send_due_digests(&self, now: chrono::DateTime<chrono::Utc>) -> usize {
+        send_due_digests(&self.db_pool, &self.email_client, &self.base_url, now)
+            .await
+            .unwrap()
+    }
+
+    /// Run one pass of the onboarding job, as if it were `now`.
+    pub async fn send_due_onboarding_emails(&self, now: chrono::DateTime<chrono::Utc>) -> usize {
+        send_due_onboarding_emails(
+            &self.db_pool,
+            &self.email_client,
+            &self.base_url,
+            &self.onboarding,
+            now,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Decide the A/B tests whose waiting time is over.
+    pub async fn decide_due_ab_tests(&self) -> usize {
+        decide_due_ab_tests(&self.db_pool).await.unwrap()
+    }
+
+    /// Extract the links embedded in an email sent to the mock email server,
+    /// pointing them at the port the test application listens on.
+    pub fn get_email_links(&self, email_request: &wiremock::Request) -> Vec<reqwest::Url> {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let content = body["content"][0]["value"].as_str().unwrap();
+
+        linkify::LinkFinder::new()
+            .links(content)
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| {
+                let mut link = reqwest::Url::parse(l.as_str()).unwrap();
+                assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+                link.set_port(Some(self.port)).unwrap();
+                link
+            })
+            .collect()
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_external_checks(ExternalChecks::default()).await
+}
+
+pub async fn spawn_app_with_external_checks(external_checks: ExternalChecks) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -43,18 +184,27 @@ pub async fn spawn_app() -> TestApp {
 
     configure_database(&configuration.database).await;
 
-    let application = Application::build(configuration.clone())
-        .await
-        .expect("Failed to build application.");
+    let application =
+        Application::build_with_external_checks(configuration.clone(), external_checks)
+            .await
+            .expect("Failed to build application.");
     let address = format!("http://127.0.0.1:{}", application.port());
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     TestApp {
-        address: address,
+        address,
         port: application_port,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
+        email_client: configuration
+            .email_client
+            .client()
+            .expect("Invalid sender email address."),
+        base_url: configuration.application.base_url,
+        admin_token: configuration.application.admin_token,
+        form_secret: configuration.subscribe_protection.form_secret,
+        onboarding: configuration.onboarding.sequence().unwrap(),
     }
 }
 
@@ -72,7 +222,7 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection =
-        PgConnection::connect(&config.connection_string_without_db().expose_secret())
+        PgConnection::connect(config.connection_string_without_db().expose_secret())
             .await
             .expect("Failed to connect to Postgres");
 
@@ -82,7 +232,7 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .expect("Failed to create database.");
 
     // Migrate database
-    let connection_pool = PgPool::connect(&config.connection_string().expose_secret())
+    let connection_pool = PgPool::connect(config.connection_string().expose_secret())
         .await
         .expect("Failed to connect to Postgres.");
 
@@ -1,5 +1,10 @@
+mod check_config;
 mod health_check;
 mod helpers;
 mod newsletter;
+mod onboarding;
+mod rate_limiting;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
@@ -8,7 +8,7 @@ use crate::helpers::{spawn_app, TestApp};
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let app = spawn_app().await;
-    create_unconfirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
 
     Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
@@ -16,21 +16,1710 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         .mount(&app.email_server)
         .await;
 
-    let newsletter_request_body = serde_json::json!({});
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"content": "<p>Newsletter body as HTML</p>"}),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": "<p>Newsletter body as HTML</p>",
+                "lists": ["not-a-list"],
+            }),
+            "unknown list",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_newsletters(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribers_of_several_target_lists_receive_the_issue_once() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    create_confirmed_subscriber(
+        &app,
+        "name=marvinhsu&email=marvinhsu@gmail.com&list=releases",
+    )
+    .await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "lists": ["newsletter", "releases"],
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_members_of_the_target_lists() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "lists": ["releases"],
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn unsubscribed_members_no_longer_receive_issues_of_that_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let unsubscribe_link = get_unsubscribe_link(&app).await;
+    let token = unsubscribe_link.query().unwrap().to_string();
+    app.post_form("/subscriptions/unsubscribe", token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn requests_without_an_admin_token_are_rejected() {
+    let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
-        .json(&newsletter_request_body)
+        .post(format!("{}/newsletters", &app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
         .send()
         .await
-        .expect("Failed to execute request.");
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Bearer realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_their_send_time_comes() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "send_at": "2999-03-07T09:00",
+            "timezone": "Europe/Berlin",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "scheduled");
+    assert_eq!(body["send_at"], "2999-03-07T08:00:00Z");
+
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        assert_eq!(app.release_due_issues().await, 0);
+        app.dispatch_all_pending_emails().await;
+    }
+
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    assert_eq!(app.release_due_issues().await, 1);
+    app.dispatch_all_pending_emails().await;
+
+    let status = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "sent");
+}
+
+#[tokio::test]
+async fn the_scheduler_completes_sending_issues_whose_queue_drained() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Drained while sending").await;
+    publish_issue(&app, "Drained while paused").await;
+    app.dispatch_all_pending_emails().await;
+    // As if the last workers had both missed that the queue ran empty.
+    sqlx::query!(
+        "UPDATE newsletter_issues SET status = CASE title \
+            WHEN 'Drained while sending' THEN 'sending' ELSE 'paused' END"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.release_due_issues().await;
+
+    // Assert
+    let statuses = sqlx::query!("SELECT title, status FROM newsletter_issues ORDER BY title")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let statuses: Vec<(&str, &str)> = statuses
+        .iter()
+        .map(|r| (r.title.as_str(), r.status.as_str()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            ("Drained while paused", "paused"),
+            ("Drained while sending", "sent")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_listed_rescheduled_and_cancelled() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "send_at": "2999-03-07T09:00:00Z",
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    let scheduled: serde_json::Value = app
+        .get_admin("/admin/newsletters/scheduled")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(scheduled.as_array().unwrap().len(), 1);
+    assert_eq!(scheduled[0]["newsletter_issue_id"], newsletter_issue_id);
+
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/reschedule", newsletter_issue_id),
+            serde_json::json!({"send_at": "2999-01-14 09:00", "timezone": "America/New_York"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let rescheduled: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(rescheduled["send_at"], "2999-01-14T14:00:00Z");
+    assert_eq!(rescheduled["timezone"], "America/New_York");
+
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/cancel", newsletter_issue_id),
+            serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let scheduled: serde_json::Value = app
+        .get_admin("/admin/newsletters/scheduled")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(scheduled.as_array().unwrap().is_empty());
+
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/reschedule", newsletter_issue_id),
+            serde_json::json!({"send_at": "2999-03-14T09:00:00Z"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publishing_with_an_invalid_send_time_is_rejected() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"send_at": "next tuesday"}),
+            "unparseable send time",
+        ),
+        (
+            serde_json::json!({"send_at": "2999-03-07T09:00", "timezone": "Mars/Olympus"}),
+            "unknown timezone",
+        ),
+    ];
+
+    for (mut body, error_message) in test_cases {
+        body["title"] = "Newsletter title".into();
+        body["content"] = "<p>Newsletter body as HTML</p>".into();
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn drafts_are_only_delivered_once_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+
+    let draft = create_draft(&app).await;
+    assert_eq!(draft["status"], "draft");
+    assert_eq!(draft["lists"], serde_json::json!(["newsletter"]));
+    let newsletter_issue_id = draft["newsletter_issue_id"].as_str().unwrap();
+
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/publish", newsletter_issue_id),
+            serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn drafts_can_only_be_edited_and_published_once() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    let draft = create_draft(&app).await;
+    let issue_path = format!(
+        "/admin/newsletters/{}",
+        draft["newsletter_issue_id"].as_str().unwrap()
+    );
+
+    let response = app
+        .put_admin(
+            &issue_path,
+            serde_json::json!({"title": "Edited title", "lists": ["releases"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let edited: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(edited["title"], "Edited title");
+    assert_eq!(edited["content"], draft["content"]);
+    assert_eq!(edited["lists"], serde_json::json!(["releases"]));
+
+    let drafts: serde_json::Value = app
+        .get_admin("/admin/newsletters/drafts")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(drafts.as_array().unwrap().len(), 1);
+
+    let response = app
+        .post_admin(
+            &format!("{}/publish", issue_path),
+            serde_json::json!({"send_at": "2999-01-05T09:00:00Z"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = app
+        .put_admin(&issue_path, serde_json::json!({"title": "Too late"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    let response = app
+        .post_admin(
+            &format!("{}/publish", issue_path),
+            serde_json::json!({"send_at": "2999-01-06T09:00:00Z"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn previews_render_the_issue_a_sample_subscriber_would_get() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    create_confirmed_subscriber(&app, "name=hsu&email=marvin%40example.com&locale=zh-TW").await;
+    let draft = create_draft_with_a_translation(&app).await;
+    let preview_path = format!(
+        "/admin/newsletters/{}/preview",
+        draft["newsletter_issue_id"].as_str().unwrap()
+    );
+
+    let response = app
+        .get_admin(&format!(
+            "{}?subscriber_email=marvinhsu%40gmail.com",
+            preview_path
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+    // Links in previews do not work on behalf of a real subscriber.
+    assert!(html.contains("subscription_token=sample"));
+    let subscription_tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    for token in subscription_tokens {
+        assert!(!html.contains(&token.subscription_token));
+    }
+
+    let response = app
+        .get_admin(&format!(
+            "{}?subscriber_email=marvin%40example.com",
+            preview_path
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<p>電子報內容</p>"));
+
+    let response = app
+        .get_admin(&format!(
+            "{}?subscriber_email=stranger%40gmail.com",
+            preview_path
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_sends_only_go_to_the_given_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let draft = create_draft(&app).await;
+    let test_path = format!(
+        "/admin/newsletters/{}/test",
+        draft["newsletter_issue_id"].as_str().unwrap()
+    );
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin(&test_path, serde_json::json!({"email": "not-an-email"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app
+        .post_admin(&test_path, serde_json::json!({"email": "editor@gmail.com"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let personalization = &body["personalizations"][0];
+    assert_eq!(personalization["to"][0]["email"], "editor@gmail.com");
+    assert_eq!(personalization["subject"], "[Test] Newsletter title");
+}
+
+#[tokio::test]
+async fn test_sends_are_in_the_language_of_the_sample_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=hsu&email=marvin%40example.com&locale=zh-TW").await;
+    let draft = create_draft_with_a_translation(&app).await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin(
+            &format!(
+                "/admin/newsletters/{}/test",
+                draft["newsletter_issue_id"].as_str().unwrap()
+            ),
+            serde_json::json!({
+                "email": "editor@gmail.com",
+                "subscriber_email": "marvin@example.com",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["personalizations"][0]["subject"], "[Test] 電子報標題");
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(html.contains("<p>電子報內容</p>"));
+    assert!(html.contains("subscription_token=sample"));
+}
+
+#[tokio::test]
+async fn published_issues_are_readable_in_the_web_archive() {
+    let app = spawn_app().await;
+    publish_issue(&app, "First issue").await;
+    publish_issue(&app, "Second issue").await;
+    create_draft(&app).await;
+
+    let archive = reqwest::get(format!("{}/newsletters", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(archive.contains("First issue"));
+    assert!(archive.contains("Second issue"));
+    assert!(!archive.contains("Newsletter title"));
+
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues WHERE title = 'First issue'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug
+        .unwrap();
+    assert!(archive.contains(&format!("/newsletters/{}", slug)));
+    let response = reqwest::get(format!("{}/newsletters/{}", &app.address, slug))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>Newsletter body as HTML</p>"));
+
+    let response = reqwest::get(format!("{}/newsletters/no-such-issue", &app.address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_web_archive_is_paginated() {
+    let app = spawn_app().await;
+    for i in 0..11 {
+        publish_issue(&app, &format!("Issue number {}", i)).await;
+    }
+
+    let first_page = reqwest::get(format!("{}/newsletters", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(first_page.matches("<li>").count(), 10);
+    assert!(first_page.contains("/newsletters?page=2"));
+
+    let second_page = reqwest::get(format!("{}/newsletters?page=2", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(second_page.matches("<li>").count(), 1);
+    assert!(second_page.contains("Issue number 0"));
+    assert!(!second_page.contains("/newsletters?page=3"));
+}
+
+#[tokio::test]
+async fn the_archive_is_available_as_an_atom_feed() {
+    let app = spawn_app().await;
+    publish_issue(&app, "First issue").await;
+
+    let response = reqwest::get(format!("{}/newsletters/feed.atom", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/atom+xml"));
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>First issue</title>"));
+    assert!(feed.contains("&lt;p&gt;Newsletter body as HTML&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn sent_issues_link_to_their_web_version() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_issue(&app, "First issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let web_link = app.get_email_links(&email_request).remove(0);
+    assert!(web_link.path().starts_with("/newsletters/first-issue-"));
+    let response = reqwest::get(web_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn opens_and_clicks_are_tracked_for_issues_that_opted_in() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": r#"<p><a href="https://example.com/post">Read more</a></p>"#,
+            "tracking": true,
+        }))
+        .await;
+    let newsletter_issue_id = response.json::<serde_json::Value>().await.unwrap()
+        ["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(!html.contains("https://example.com/post"));
+    assert!(!html.contains("marvinhsu"));
+    let links = app.get_email_links(&email_request);
+    let click_link = links
+        .iter()
+        .find(|l| l.path().starts_with("/t/c/"))
+        .unwrap();
+    let open_link = links
+        .iter()
+        .find(|l| l.path().starts_with("/t/o/"))
+        .unwrap();
+
+    let response = reqwest::get(open_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(click_link.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["Location"], "https://example.com/post");
+
+    let report: serde_json::Value = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/engagement",
+            newsletter_issue_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["recipients"], 1);
+    assert_eq!(report["unique_opens"], 1);
+    assert_eq!(report["unique_clicks"], 1);
+    assert_eq!(report["open_rate"], 1.0);
+    assert_eq!(report["links"][0]["url"], "https://example.com/post");
+    assert_eq!(report["links"][0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_by_default() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": r#"<p><a href="https://example.com/post">Read more</a></p>"#,
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let html = std::str::from_utf8(&email_request.body).unwrap();
+    assert!(html.contains("https://example.com/post"));
+    assert!(!html.contains("/t/o/"));
+    assert!(!html.contains("/t/c/"));
+}
+
+#[tokio::test]
+async fn unknown_tracking_ids_are_not_recorded() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/t/c/unknown.0", &app.address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let response = reqwest::get(format!("{}/t/o/unknown", &app.address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM engagement_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(events, 0);
+}
+
+#[tokio::test]
+async fn delivery_progress_is_reported_per_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let status_path = format!("/admin/newsletters/{}/status", newsletter_issue_id);
+    let progress: serde_json::Value = app.get_admin(&status_path).await.json().await.unwrap();
+    assert_eq!(progress["issue_status"], "sending");
+    assert_eq!(progress["queued"], 1);
+
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app.get_admin(&status_path).await.json().await.unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+    assert_eq!(progress["total"], 1);
+    assert_eq!(progress["sent"], 1);
+    assert_eq!(progress["queued"], 0);
+    assert_eq!(progress["estimated_seconds_remaining"], 0);
+    assert!(progress["last_errors"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_before_giving_up() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let status_path = format!("/admin/newsletters/{}/status", newsletter_issue_id);
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app.get_admin(&status_path).await.json().await.unwrap();
+    assert_eq!(progress["issue_status"], "sending");
+    assert_eq!(progress["retrying"], 1);
+    assert_eq!(progress["last_errors"].as_array().unwrap().len(), 1);
+
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now(), n_retries = 3")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app.get_admin(&status_path).await.json().await.unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+    assert_eq!(progress["failed"], 1);
+    assert_eq!(progress["retrying"], 0);
+}
+
+#[tokio::test]
+async fn rejected_recipients_are_reported_as_bounced() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/status",
+            newsletter_issue_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["bounced"], 1);
+    assert_eq!(progress["issue_status"], "sent");
+}
+
+#[tokio::test]
+async fn delivery_progress_is_streamed_as_server_sent_events() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+
+    let response = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/status/stream",
+            newsletter_issue_id
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+    // Nobody is subscribed, so the issue is sent already and the stream ends
+    // after a single event.
+    let events = response.text().await.unwrap();
+    assert!(events.starts_with("event: progress\ndata: {"));
+    assert!(events.contains(r#""issue_status":"sent""#));
+}
+
+#[tokio::test]
+async fn paused_deliveries_resume_where_they_left_off() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    create_confirmed_subscriber(&app, "name=ursula&email=ursula_le_guin@gmail.com").await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let issue_path = format!("/admin/newsletters/{}", newsletter_issue_id);
+
+    let response = app
+        .post_admin(&format!("{}/pause", issue_path), serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "paused");
+    assert_eq!(progress["queued"], 2);
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_admin(&format!("{}/resume", issue_path), serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+    assert_eq!(progress["sent"], 2);
+}
+
+#[tokio::test]
+async fn cancelling_a_delivery_cancels_what_is_left_of_it() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let issue_path = format!("/admin/newsletters/{}", newsletter_issue_id);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin(&format!("{}/cancel", issue_path), serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    app.dispatch_all_pending_emails().await;
+
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "cancelled");
+    assert_eq!(progress["cancelled"], 1);
+    assert_eq!(progress["queued"], 0);
+
+    let response = app
+        .post_admin(&format!("{}/resume", issue_path), serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+/// Cancel the issue while the worker waits for the email provider to answer
+/// with `status`.
+async fn cancel_during_delivery(app: &TestApp, newsletter_issue_id: &str, status: u16) {
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(status).set_delay(std::time::Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let cancel = async {
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        app.post_admin(
+            &format!("/admin/newsletters/{}/cancel", newsletter_issue_id),
+            serde_json::json!({}),
+        )
+        .await
+    };
+
+    let (_, response) = tokio::join!(app.dispatch_all_pending_emails(), cancel);
+
+    assert_eq!(response.status().as_u16(), 204);
+}
+
+#[tokio::test]
+async fn cancelling_does_not_wait_for_the_tasks_workers_hold() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+
+    cancel_during_delivery(&app, &newsletter_issue_id, 200).await;
+
+    let progress: serde_json::Value = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/status",
+            newsletter_issue_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "cancelled");
+    assert_eq!(progress["sent"], 1);
+}
+
+#[tokio::test]
+async fn tasks_failing_while_their_issue_is_cancelled_are_not_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+
+    cancel_during_delivery(&app, &newsletter_issue_id, 500).await;
+
+    let progress: serde_json::Value = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/status",
+            newsletter_issue_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "cancelled");
+    assert_eq!(progress["cancelled"], 1);
+    assert_eq!(progress["retrying"], 0);
+}
+
+#[tokio::test]
+async fn resuming_a_delivery_drained_while_paused_completes_it() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let issue_path = format!("/admin/newsletters/{}", newsletter_issue_id);
+    app.post_admin(&format!("{}/pause", issue_path), serde_json::json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+    // The workers deliver the tasks they held when the issue got paused.
+    sqlx::query!("UPDATE issue_delivery_queue SET status = 'sent'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_admin(&format!("{}/resume", issue_path), serde_json::json!({}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 204);
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+}
+
+#[tokio::test]
+async fn only_running_deliveries_can_be_paused() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/pause", newsletter_issue_id),
+            serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let response = app
+        .post_admin(
+            &format!("/admin/newsletters/{}/pause", uuid::Uuid::new_v4()),
+            serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_rest_of_the_list_gets_the_subject_line_with_the_best_open_rate() {
+    let app = spawn_app().await;
+    for name in ["marvinhsu", "ursula", "octavia", "becky"] {
+        create_confirmed_subscriber(&app, &format!("name={0}&email={0}@gmail.com", name)).await;
+    }
+    let draft = create_draft(&app).await;
+    let issue_path = format!(
+        "/admin/newsletters/{}",
+        draft["newsletter_issue_id"].as_str().unwrap()
+    );
+    let response = app
+        .put_admin(
+            &format!("{}/ab_test", issue_path),
+            serde_json::json!({
+                "subjects": ["Subject A", "Subject B"],
+                "sample_percent": 50,
+                "wait_minutes": 60,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_admin(&format!("{}/publish", issue_path), serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Only the sample gets the issue, one subscriber per subject line.
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(issue_subjects(&app).await, vec!["Subject A", "Subject B"]);
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "sending");
+    assert_eq!(progress["held"], 2);
+    assert_eq!(app.decide_due_ab_tests().await, 0);
+
+    // The recipient of the second subject line opens the issue.
+    let tracking_token = sqlx::query!(
+        r#"
+SELECT issue_recipients.tracking_token AS "tracking_token!"
+FROM issue_recipients
+JOIN issue_delivery_queue
+    ON issue_delivery_queue.newsletter_issue_id = issue_recipients.newsletter_issue_id
+    AND issue_delivery_queue.subscriber_id = issue_recipients.subscriber_id
+WHERE issue_delivery_queue.variant_index = 1
+"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .tracking_token;
+    reqwest::get(format!("{}/t/o/{}", app.address, tracking_token))
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE ab_tests SET decide_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(app.decide_due_ab_tests().await, 1);
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        issue_subjects(&app).await,
+        vec!["Subject A", "Subject B", "Subject B", "Subject B"]
+    );
+    let report: serde_json::Value = app
+        .get_admin(&format!("{}/ab_test", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["winning_variant"], 1);
+    assert_eq!(report["variants"][1]["subject"], "Subject B");
+    assert_eq!(report["variants"][1]["opens"], 1);
+    assert_eq!(report["variants"][0]["open_rate"], 0.0);
+    let received = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE variant_index = 1 AND status = 'sent'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(received.count, 3);
+    let progress: serde_json::Value = app
+        .get_admin(&format!("{}/status", issue_path))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+}
+
+#[tokio::test]
+async fn invalid_ab_tests_are_rejected() {
+    let app = spawn_app().await;
+    let draft = create_draft(&app).await;
+    let ab_test_path = format!(
+        "/admin/newsletters/{}/ab_test",
+        draft["newsletter_issue_id"].as_str().unwrap()
+    );
+    let test_cases = vec![
+        (
+            serde_json::json!({"subjects": ["Only one"], "sample_percent": 20, "wait_minutes": 60}),
+            "a single subject line",
+        ),
+        (
+            serde_json::json!({"subjects": ["A", "B"], "sample_percent": 80, "wait_minutes": 60}),
+            "a sample of most of the list",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = app.put_admin(&ab_test_path, body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject an A/B test with {}.",
+            description
+        );
+    }
+
+    let newsletter_issue_id = publish_issue(&app, "First issue").await;
+    let response = app
+        .put_admin(
+            &format!("/admin/newsletters/{}/ab_test", newsletter_issue_id),
+            serde_json::json!({"subjects": ["A", "B"], "sample_percent": 20, "wait_minutes": 60}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn segments_narrow_down_the_audience_of_an_issue() {
+    let app = spawn_app().await;
+    for (name, attributes) in [
+        ("marvinhsu", serde_json::json!({"country": "TW", "age": 31})),
+        ("ursula", serde_json::json!({"country": "TW", "age": "40"})),
+        ("octavia", serde_json::json!({"country": "US", "age": 45})),
+    ] {
+        create_confirmed_subscriber(&app, &format!("name={0}&email={0}@gmail.com", name)).await;
+        let subscriber_id = sqlx::query!("SELECT id FROM subscriptions WHERE name = $1", name)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .id;
+        let response = app
+            .put_admin(
+                &format!("/admin/subscribers/{}/attributes", subscriber_id),
+                attributes,
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Ursula's age is text, which numeric comparisons leave out.
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "segment": "status = confirmed AND custom.country = 'TW' AND custom.age >= 18",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let recipient = sqlx::query!(
+        r#"
+SELECT subscriptions.name AS "name!"
+FROM issue_delivery_queue
+JOIN subscriptions ON subscriptions.id = issue_delivery_queue.subscriber_id
+"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(recipient.name, "marvinhsu");
+}
+
+#[tokio::test]
+async fn invalid_segments_and_attributes_are_rejected() {
+    let app = spawn_app().await;
+    for segment in [
+        "custom.country = ",
+        "country = 'TW'",
+        "subscribed_at > soon",
+    ] {
+        let response = app
+            .post_newsletters(serde_json::json!({
+                "title": "Newsletter title",
+                "content": "<p>Newsletter body as HTML</p>",
+                "segment": segment,
+            }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API accepted the segment {}.",
+            segment
+        );
+    }
+
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    for attributes in [
+        serde_json::json!(["TW"]),
+        serde_json::json!({"home country": "TW"}),
+        serde_json::json!({"address": {"country": "TW"}}),
+    ] {
+        let response = app
+            .put_admin(
+                &format!("/admin/subscribers/{}/attributes", subscriber_id),
+                attributes,
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    let response = app
+        .put_admin(
+            &format!("/admin/subscribers/{}/attributes", uuid::Uuid::new_v4()),
+            serde_json::json!({"country": "TW"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn digest_subscribers_get_the_issues_of_the_period_in_one_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=marvinhsu&email=marvinhsu@gmail.com").await;
+    create_confirmed_subscriber(&app, "name=ursula&email=ursula_le_guin@gmail.com").await;
+    sqlx::query!("UPDATE subscriptions SET delivery_cadence = 'weekly' WHERE name = 'ursula'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let first_issue_id = publish_issue(&app, "First issue").await;
+    publish_issue(&app, "Second issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    let recipients = |requests: Vec<wiremock::Request>| {
+        requests
+            .iter()
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body["personalizations"][0]["to"][0]["email"]
+                    .as_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .filter(|email| email != "editor@gmail.com")
+            .collect::<Vec<_>>()
+    };
+    let n_confirmation_emails = 2;
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(
+        recipients(requests[n_confirmation_emails..].to_vec()),
+        vec!["marvinhsu@gmail.com", "marvinhsu@gmail.com"]
+    );
+    let progress: serde_json::Value = app
+        .get_admin(&format!("/admin/newsletters/{}/status", first_issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["issue_status"], "sent");
+    assert_eq!(progress["digest"], 1);
+
+    // Nothing goes out before the week is over.
+    assert_eq!(app.send_due_digests(chrono::Utc::now()).await, 0);
+    let next_week = chrono::Utc::now() + chrono::Duration::days(8);
+    assert_eq!(app.send_due_digests(next_week).await, 1);
+    assert_eq!(app.send_due_digests(next_week).await, 0);
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let digest_request = requests.last().unwrap();
+    assert_eq!(
+        recipients(vec![digest_request.clone()]),
+        vec!["ursula_le_guin@gmail.com"]
+    );
+    let body: serde_json::Value = serde_json::from_slice(&digest_request.body).unwrap();
+    assert_eq!(body["personalizations"][0]["subject"], "Your weekly digest");
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(html.find("First issue").unwrap() < html.find("Second issue").unwrap());
+    let delivered = sqlx::query!(
+        r#"
+SELECT COUNT(*) AS "count!"
+FROM issue_delivery_queue
+WHERE status = 'sent' AND digest_id IS NOT NULL
+"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivered.count, 2);
+}
+
+#[tokio::test]
+async fn digests_leave_out_the_issues_of_a_list_the_subscriber_has_left() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=ursula&email=ursula_le_guin@gmail.com").await;
+    sqlx::query!("UPDATE subscriptions SET delivery_cadence = 'weekly'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "First issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    let token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    app.post_form(
+        "/subscriptions/unsubscribe",
+        format!("subscription_token={}", token),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let next_week = chrono::Utc::now() + chrono::Duration::days(8);
+    assert_eq!(app.send_due_digests(next_week).await, 0);
+}
+
+#[tokio::test]
+async fn clicks_in_a_translation_go_to_the_links_of_the_translation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=hsu&email=marvin%40example.com&locale=zh-TW").await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": r#"<p><a href="https://example.com/post">Read more</a></p>"#,
+            "tracking": true,
+            "variants": [{
+                "locale": "zh-TW",
+                "title": "電子報標題",
+                "content": r#"<p><a href="https://example.com/zh/about">關於</a>
+                    <a href="https://example.com/zh/post">閱讀更多</a></p>"#,
+            }],
+        }))
+        .await;
+    let newsletter_issue_id = response.json::<serde_json::Value>().await.unwrap()
+        ["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let click_links: Vec<reqwest::Url> = app
+        .get_email_links(&email_request)
+        .into_iter()
+        .filter(|l| l.path().starts_with("/t/c/"))
+        .collect();
+    assert_eq!(click_links.len(), 2);
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let mut locations = vec![];
+    for click_link in click_links {
+        let response = client.get(click_link).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 302);
+        locations.push(response.headers()["Location"].to_str().unwrap().to_owned());
+    }
+    assert_eq!(
+        locations,
+        [
+            "https://example.com/zh/about",
+            "https://example.com/zh/post"
+        ]
+    );
+
+    let report: serde_json::Value = app
+        .get_admin(&format!(
+            "/admin/newsletters/{}/engagement",
+            newsletter_issue_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["links"][0]["locale"], serde_json::Value::Null);
+    assert_eq!(report["links"][0]["clicks"], 0);
+    assert_eq!(report["links"][1]["locale"], "zh-TW");
+    assert_eq!(report["links"][1]["url"], "https://example.com/zh/about");
+    assert_eq!(report["links"][1]["clicks"], 1);
+    assert_eq!(report["links"][2]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn subscribers_get_the_variant_of_an_issue_in_their_language() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "name=le%20guin&email=ursula%40example.com").await;
+    create_confirmed_subscriber(&app, "name=hsu&email=marvin%40example.com&locale=zh-TW").await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let n_confirmations = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "variants": [{
+                "locale": "zh-tw",
+                "title": "電子報標題",
+                "content": "<p>電子報內容</p>",
+            }],
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let issues = app.email_server.received_requests().await.unwrap()[n_confirmations..].to_vec();
+    let mut received: Vec<(String, String)> = issues
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            (
+                body["personalizations"][0]["to"][0]["email"]
+                    .as_str()
+                    .unwrap()
+                    .to_owned(),
+                body["personalizations"][0]["subject"]
+                    .as_str()
+                    .unwrap()
+                    .to_owned(),
+            )
+        })
+        .collect();
+    received.sort();
+    assert_eq!(
+        received,
+        vec![
+            ("marvin@example.com".to_owned(), "電子報標題".to_owned()),
+            (
+                "ursula@example.com".to_owned(),
+                "Newsletter title".to_owned()
+            ),
+        ]
+    );
+    let translated = issues
+        .into_iter()
+        .map(|r| String::from_utf8(r.body).unwrap())
+        .find(|body| body.contains("電子報內容"))
+        .unwrap();
+    assert!(translated.contains("取消訂閱"));
+}
+
+#[tokio::test]
+async fn variants_in_unsupported_or_repeated_locales_are_rejected() {
+    let app = spawn_app().await;
+    let variant = |locale: &str| serde_json::json!({ "locale": locale, "title": "Title", "content": "<p>Body</p>" });
+
+    let unsupported = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "variants": [variant("tlh")],
+        }))
+        .await;
+    let repeated = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "variants": [variant("zh-TW"), variant("zh-tw")],
+        }))
+        .await;
+
+    assert_eq!(unsupported.status().as_u16(), 400);
+    assert_eq!(repeated.status().as_u16(), 400);
+}
+
+/// The subject lines of the issues sent so far, leaving out confirmation
+/// emails, in alphabetical order.
+async fn issue_subjects(app: &TestApp) -> Vec<String> {
+    let mut subjects: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["personalizations"][0]["subject"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        })
+        .filter(|s| s.starts_with("Subject"))
+        .collect();
+    subjects.sort();
+    subjects
+}
 
+async fn publish_issue(app: &TestApp, title: &str) -> String {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": title,
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+async fn create_draft_with_a_translation(app: &TestApp) -> serde_json::Value {
+    let draft = create_draft(app).await;
+    let response = app
+        .put_admin(
+            &format!(
+                "/admin/newsletters/{}",
+                draft["newsletter_issue_id"].as_str().unwrap()
+            ),
+            serde_json::json!({
+                "variants": [{
+                    "locale": "zh-TW",
+                    "title": "電子報標題",
+                    "content": "<p>電子報內容</p>",
+                }],
+            }),
+        )
+        .await;
     assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) {
-    let body = "name=marvinhsu&email=marvinhsu@gmail.com";
+async fn create_draft(app: &TestApp) -> serde_json::Value {
+    let response = app
+        .post_admin(
+            "/admin/newsletters",
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+async fn create_list(app: &TestApp, slug: &str) {
+    sqlx::query!(
+        "INSERT INTO lists (id, slug, name, created_at) VALUES (gen_random_uuid(), $1, $1, now())",
+        slug
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
 
+async fn create_unconfirmed_subscriber(app: &TestApp, body: &str) -> reqwest::Url {
     let _mock_guard = Mock::given(path("/v3/mail/send"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
@@ -43,4 +1732,49 @@ async fn create_unconfirmed_subscriber(app: &TestApp) {
         .await
         .error_for_status()
         .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_email_links(&email_request).pop().unwrap()
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, body: &str) {
+    let confirmation_link = create_unconfirmed_subscriber(app, body).await;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn get_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_email_links(&email_request).pop().unwrap()
 }
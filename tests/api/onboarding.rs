@@ -0,0 +1,214 @@
+use chrono::{Duration, Utc};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+/// Subscribes and confirms someone, returning the link of their confirmation
+/// email.
+async fn create_confirmed_subscriber(app: &TestApp, body: &str) -> reqwest::Url {
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_email_links(&email_request).pop().unwrap();
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    confirmation_link
+}
+
+async fn subjects_sent(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["personalizations"][0]["subject"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect()
+}
+
+async fn onboarding_status(app: &TestApp) -> (String, i32) {
+    let progress = sqlx::query!("SELECT status, next_step FROM onboarding_progress")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (progress.status, progress.next_step)
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_get_the_welcome_email_then_each_step_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_confirmed_subscriber(&app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    let confirmed_at = Utc::now();
+
+    // Act - Part 1 - The welcome email goes out right away, once
+    assert_eq!(app.send_due_onboarding_emails(confirmed_at).await, 1);
+    assert_eq!(app.send_due_onboarding_emails(confirmed_at).await, 0);
+
+    // Act - Part 2 - The next step waits for its delay
+    let next_day = confirmed_at + Duration::hours(25);
+    assert_eq!(app.send_due_onboarding_emails(next_day).await, 1);
+    assert_eq!(app.send_due_onboarding_emails(next_day).await, 0);
+
+    // Act - Part 3 - Steps left behind go out one per run
+    let next_week = confirmed_at + Duration::days(8);
+    assert_eq!(app.send_due_onboarding_emails(next_week).await, 1);
+    assert_eq!(app.send_due_onboarding_emails(next_week).await, 1);
+    assert_eq!(app.send_due_onboarding_emails(next_week).await, 0);
+
+    // Assert
+    assert_eq!(
+        subjects_sent(&app).await[1..],
+        [
+            "Welcome aboard",
+            "Getting the most out of the newsletter",
+            "Catch up on past issues",
+            "Make the newsletter yours",
+        ]
+    );
+    let welcome_email = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&welcome_email.body).unwrap();
+    let content = body["content"][0]["value"].as_str().unwrap();
+    assert!(content.contains("Hi le guin,"));
+    assert!(content.contains("/subscriptions/unsubscribe?subscription_token="));
+    assert_eq!(onboarding_status(&app).await, ("completed".into(), 4));
+}
+
+#[tokio::test]
+async fn confirming_twice_does_not_restart_the_sequence() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let confirmation_link =
+        create_confirmed_subscriber(&app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    app.send_due_onboarding_emails(Utc::now()).await;
+
+    // Act
+    reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(app.send_due_onboarding_emails(Utc::now()).await, 0);
+    assert_eq!(onboarding_status(&app).await, ("active".into(), 1));
+}
+
+#[tokio::test]
+async fn the_sequence_stops_when_the_subscriber_unsubscribes() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_confirmed_subscriber(&app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    assert_eq!(app.send_due_onboarding_emails(Utc::now()).await, 1);
+    let welcome_email = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = app
+        .get_email_links(&welcome_email)
+        .into_iter()
+        .find(|link| link.path() == "/subscriptions/unsubscribe")
+        .unwrap();
+
+    // Act
+    app.post_form(
+        "/subscriptions/unsubscribe",
+        unsubscribe_link.query().unwrap().to_string(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let next_week = Utc::now() + Duration::days(8);
+    assert_eq!(app.send_due_onboarding_emails(next_week).await, 0);
+    assert_eq!(onboarding_status(&app).await, ("stopped".into(), 1));
+}
+
+#[tokio::test]
+async fn onboarding_emails_are_worded_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_confirmed_subscriber(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=zh-TW",
+    )
+    .await;
+
+    // Act
+    app.send_due_onboarding_emails(Utc::now()).await;
+
+    // Assert
+    assert_eq!(subjects_sent(&app).await.last().unwrap(), "歡迎加入");
+}
+
+#[tokio::test]
+async fn failed_onboarding_emails_are_retried_on_the_next_run() {
+    // Arrange
+    let app = spawn_app().await;
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    create_confirmed_subscriber(&app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    drop(_mock_guard);
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let failed = app.send_due_onboarding_emails(Utc::now()).await;
+    let retried = app.send_due_onboarding_emails(Utc::now()).await;
+
+    // Assert
+    assert_eq!(failed, 0);
+    assert_eq!(retried, 1);
+    assert_eq!(onboarding_status(&app).await, ("active".into(), 1));
+}
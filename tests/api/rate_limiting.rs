@@ -0,0 +1,103 @@
+use crate::helpers::spawn_app;
+use chrono::{Duration, TimeZone, Utc};
+use zero2prod_practice::rate_limiting::{PostgresStore, RateLimitStore};
+
+#[tokio::test]
+async fn requests_over_the_limit_of_a_route_get_a_429() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let confirm = |forwarded_for: String| {
+        client
+            .get(format!("{}/subscriptions/confirm", app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .send()
+    };
+    // The limit is 30 requests a minute.
+    for i in 0..30 {
+        let response = confirm(format!("203.0.113.{}", i)).await.unwrap();
+        assert_ne!(429, response.status().as_u16());
+    }
+
+    // Act
+    // X-Forwarded-For is not trusted without proxies in front.
+    let response = confirm("203.0.113.99".into()).await.unwrap();
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: i64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    // Other routes are not affected.
+    let response = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_postgres_store_counts_hits_per_window() {
+    // Arrange
+    let app = spawn_app().await;
+    let store = PostgresStore::new(app.db_pool.clone());
+    let first = Utc.with_ymd_and_hms(2023, 1, 17, 12, 0, 0).unwrap();
+    let second = first + Duration::seconds(60);
+    let hit = |window_start| {
+        store.hit(
+            "0:127.0.0.1",
+            window_start,
+            window_start + Duration::seconds(60),
+        )
+    };
+
+    // Act
+    let hits = vec![
+        hit(first).await.unwrap(),
+        hit(first).await.unwrap(),
+        hit(second).await.unwrap(),
+    ];
+
+    // Assert
+    assert_eq!(hits, vec![1, 2, 1]);
+}
+
+#[tokio::test]
+async fn the_postgres_store_spaces_gcra_requests_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let store = PostgresStore::new(app.db_pool.clone());
+    let now = Utc.with_ymd_and_hms(2023, 1, 17, 12, 0, 0).unwrap();
+    // 2 requests a minute: one every 30 seconds.
+    let arrive = |now| {
+        store.arrive(
+            "0:127.0.0.1",
+            now,
+            Duration::seconds(30),
+            Duration::seconds(60),
+        )
+    };
+
+    // Act
+    let waits = vec![
+        arrive(now).await.unwrap(),
+        arrive(now).await.unwrap(),
+        arrive(now).await.unwrap(),
+        arrive(now + Duration::seconds(10)).await.unwrap(),
+        arrive(now + Duration::seconds(30)).await.unwrap(),
+    ];
+
+    // Assert
+    assert_eq!(
+        waits,
+        vec![
+            None,
+            None,
+            Some(Duration::seconds(30)),
+            Some(Duration::seconds(20)),
+            None
+        ]
+    );
+}
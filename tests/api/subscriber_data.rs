@@ -0,0 +1,332 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+const SUBSCRIBER: &str = "name=hsu%20marvin&email=marvin_hsu%40gmail.com";
+const EMAIL: &str = "email=marvin_hsu%40gmail.com";
+
+#[tokio::test]
+async fn data_export_request_sends_an_email_with_a_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_form("/subscriptions/data_export", EMAIL.into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    assert_eq!(app.get_email_links(email_request).len(), 1);
+}
+
+#[tokio::test]
+async fn data_requests_for_unknown_emails_do_not_send_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for request_path in ["/subscriptions/data_export", "/subscriptions/erasure"] {
+        // Act
+        let response = app.post_form(request_path, EMAIL.into()).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn the_export_link_returns_the_stored_data() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let export_link = request_link(&app, "/subscriptions/data_export").await;
+
+    // Act
+    let response = reqwest::get(export_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], "marvin_hsu@gmail.com");
+    assert_eq!(export["subscriber"]["name"], "hsu marvin");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["consent_events"][0]["event_type"], "requested");
+    assert_eq!(export["consent_events"][0]["list"], "newsletter");
+}
+
+#[tokio::test]
+async fn the_export_includes_the_delivery_and_engagement_history() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let confirmation_email = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_email_links(confirmation_email).pop().unwrap();
+    let mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.send_due_onboarding_emails(chrono::Utc::now()).await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+        "tracking": true,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let issue_email = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let open_link = app
+        .get_email_links(&issue_email)
+        .into_iter()
+        .find(|link| link.path().starts_with("/t/o/"))
+        .unwrap();
+    reqwest::get(open_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    drop(mock_guard);
+    let export_link = request_link(&app, "/subscriptions/data_export").await;
+
+    // Act
+    let response = reqwest::get(export_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["deliveries"][0]["title"], "Newsletter title");
+    assert_eq!(export["deliveries"][0]["status"], "sent");
+    let issue_id = &export["deliveries"][0]["newsletter_issue_id"];
+    assert_eq!(
+        &export["issues_received"][0]["newsletter_issue_id"],
+        issue_id
+    );
+    assert_eq!(
+        &export["engagement_events"][0]["newsletter_issue_id"],
+        issue_id
+    );
+    assert_eq!(export["engagement_events"][0]["kind"], "open");
+    assert_eq!(export["digests"], serde_json::json!([]));
+    assert_eq!(export["onboarding"]["next_step"], 1);
+    assert_eq!(export["onboarding"]["status"], "active");
+}
+
+#[tokio::test]
+async fn export_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let export_link = request_link(&app, "/subscriptions/data_export").await;
+    reqwest::get(export_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(export_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirming_an_erasure_removes_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let erasure_link = request_link(&app, "/subscriptions/erasure").await;
+    let token = erasure_link
+        .query_pairs()
+        .find(|(key, _)| key == "data_request_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let response = app
+        .post_form(
+            "/subscriptions/erasure/confirm",
+            format!("data_request_token={}", token),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_with_an_invalid_stored_email_are_erased_too() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let erasure_link = request_link(&app, "/subscriptions/erasure").await;
+    let token = erasure_link.query().unwrap().to_string();
+    // Stored before the current validation rules.
+    sqlx::query!("UPDATE subscriptions SET email = 'not-an-email'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_form("/subscriptions/erasure/confirm", token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    let suppressed = sqlx::query!("SELECT email_hash FROM suppressed_emails")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed.len(), 1);
+}
+
+#[tokio::test]
+async fn an_erased_email_is_not_subscribed_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let erasure_link = request_link(&app, "/subscriptions/erasure").await;
+    let token = erasure_link.query().unwrap().to_string();
+    app.post_form("/subscriptions/erasure/confirm", token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(SUBSCRIBER.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn erasing_an_address_does_not_suppress_other_cases_of_its_local_part() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let erasure_link = request_link(&app, "/subscriptions/erasure").await;
+    let token = erasure_link.query().unwrap().to_string();
+    app.post_form("/subscriptions/erasure/confirm", token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    // The domain is not case-sensitive, the local part is.
+    let same_address = app
+        .post_subscriptions("name=hsu%20marvin&email=marvin_hsu%40GMAIL.com".into())
+        .await;
+    let other_address = app
+        .post_subscriptions("name=hsu%20marvin&email=Marvin_Hsu%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(same_address.status().as_u16(), 200);
+    assert_eq!(other_address.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let emails: Vec<&str> = subscribers.iter().map(|r| r.email.as_str()).collect();
+    assert_eq!(emails, ["Marvin_Hsu@gmail.com"]);
+}
+
+async fn create_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(SUBSCRIBER.into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn request_link(app: &TestApp, request_path: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_form(request_path, EMAIL.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_email_links(&email_request).pop().unwrap()
+}
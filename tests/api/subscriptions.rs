@@ -1,6 +1,16 @@
-use crate::helpers::spawn_app;
+use std::sync::Arc;
+
+use crate::helpers::{spawn_app, spawn_app_with_external_checks};
+use chrono::{Duration, Utc};
+use futures_util::future::BoxFuture;
+use secrecy::Secret;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod_practice::{
+    bot_protection::{issue_form_token, CaptchaVerifier},
+    deliverability::DomainResolver,
+    startup::ExternalChecks,
+};
 
 #[tokio::test]
 async fn subscribe_return_a_200_for_valid_form_data() {
@@ -66,6 +76,61 @@ async fn subscribe_return_a_400_when_data_is_missing() {
     }
 }
 
+#[tokio::test]
+async fn addresses_differing_only_in_the_case_of_their_domain_are_the_same_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("Post"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=hsu%20marvin&email=Marvin_Hsu%40GMail.com".into())
+        .await;
+    app.post_subscriptions("name=hsu%20marvin&email=Marvin_Hsu%40gmail.COM".into())
+        .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, email_original FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Marvin_Hsu@gmail.com");
+    assert_eq!(saved[0].email_original, "Marvin_Hsu@GMail.com");
+}
+
+#[tokio::test]
+async fn addresses_differing_in_the_case_of_their_local_part_are_different_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("Post"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=hsu%20marvin&email=Marvin_Hsu%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions("name=hsu%20marvin&email=marvin_hsu%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    let emails: Vec<&str> = saved.iter().map(|r| r.email.as_str()).collect();
+    assert_eq!(emails, ["Marvin_Hsu@gmail.com", "marvin_hsu@gmail.com"]);
+}
+
 #[tokio::test]
 async fn subscribe_return_a_400_when_fields_are_present_but_empty() {
     // Arrange
@@ -77,6 +142,19 @@ async fn subscribe_return_a_400_when_fields_are_present_but_empty() {
             "name=marvinhsu&email=definitely-not-an-email",
             "invalid email",
         ),
+        (
+            "name=marvinhsu&email=marvin%40mailinator.com",
+            "disposable email",
+        ),
+        (
+            "name=marvinhsu&email=postmaster%40gmail.com",
+            "role account",
+        ),
+        // The first letter is a Cyrillic `а`.
+        (
+            "name=%D0%B0dmin&email=marvin_hsu%40gmail.com",
+            "name mixing scripts",
+        ),
     ];
 
     for (body, description) in test_case {
@@ -139,3 +217,427 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     assert_eq!(links.len(), 1);
 }
+
+#[tokio::test]
+async fn subscribe_records_the_consent_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=hsu%20marvin&email=marvin_hsu%40gmail.com&source=footer_form";
+    Mock::given(path("/v3/mail/send"))
+        .and(method("Post"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "consent-test-agent")
+        .body(format!("{}&form_token={}", body, app.form_token()))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT event_type, ip_address, user_agent, source, consent_text_version FROM consent_events"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent event.");
+
+    assert_eq!(saved.event_type, "requested");
+    assert_eq!(saved.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(saved.user_agent.as_deref(), Some("consent-test-agent"));
+    assert_eq!(saved.source.as_deref(), Some("footer_form"));
+    assert!(!saved.consent_text_version.is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_return_a_400_for_an_unknown_list() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=hsu%20marvin&email=marvin_hsu%40gmail.com&list=not-a-list";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_ignores_submissions_filling_in_the_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.test".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn subscribe_ignores_forms_submitted_too_fast() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let form_token = issue_form_token(&app.form_secret, Utc::now());
+
+    // Act
+    let response = app
+        .post_form(
+            "/subscriptions",
+            format!(
+                "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+                form_token
+            ),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_missing_or_forged_form_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    let forged = issue_form_token(
+        &Secret::new("not-the-form-secret".into()),
+        Utc::now() - Duration::seconds(60),
+    );
+    let expired = issue_form_token(&app.form_secret, Utc::now() - Duration::days(2));
+
+    for (body, description) in [
+        (String::new(), "missing"),
+        (format!("&form_token={}", forged), "forged"),
+        (format!("&form_token={}", expired), "expired"),
+    ] {
+        // Act
+        let response = app
+            .post_form(
+                "/subscriptions",
+                format!("name=le%20guin&email=ursula_le_guin%40gmail.com{}", body),
+            )
+            .await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject a {} form token.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rate_limits_attempts_for_the_same_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    for _ in 0..5 {
+        assert_eq!(
+            200,
+            app.post_subscriptions(body.into()).await.status().as_u16()
+        );
+    }
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: i64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn the_subscribe_form_carries_a_form_token_and_a_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/subscriptions", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"name="form_token""#));
+    assert!(html.contains(r#"name="website""#));
+}
+
+struct FakeCaptchaVerifier;
+
+impl CaptchaVerifier for FakeCaptchaVerifier {
+    fn verify<'a>(
+        &'a self,
+        captcha_response: &'a str,
+        _remote_ip: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, reqwest::Error>> {
+        Box::pin(async move { Ok(captcha_response == "solved") })
+    }
+}
+
+#[tokio::test]
+async fn subscribe_checks_the_captcha_when_one_is_configured() {
+    // Arrange
+    let app = spawn_app_with_external_checks(ExternalChecks {
+        captcha_verifier: Some(Arc::new(FakeCaptchaVerifier)),
+        ..Default::default()
+    })
+    .await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // Act
+    let missing = app.post_subscriptions(body.into()).await;
+    let failed = app
+        .post_subscriptions(format!("{}&captcha_response=robot", body))
+        .await;
+    let solved = app
+        .post_subscriptions(format!("{}&captcha_response=solved", body))
+        .await;
+
+    // Assert
+    assert_eq!(400, missing.status().as_u16());
+    assert_eq!(400, failed.status().as_u16());
+    assert_eq!(200, solved.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_suggests_a_fix_for_mistyped_domains() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmial.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"]["email"]["code"], "mistyped");
+    assert_eq!(
+        body["fields"]["email"]["suggestion"],
+        "ursula_le_guin@gmail.com"
+    );
+}
+
+#[tokio::test]
+async fn subscribe_reports_what_is_wrong_with_each_field() {
+    // Arrange
+    let app = spawn_app().await;
+    let name = "a".repeat(257);
+
+    // Act
+    let response = app
+        .post_subscriptions(format!("name={}&email=not-an-email", name))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["fields"]["name"],
+        serde_json::json!({
+            "code": "too_long",
+            "max": 256,
+            "actual": 257,
+            "message": "The name is 257 characters long, it can be at most 256.",
+        })
+    );
+    assert_eq!(body["fields"]["email"]["code"], "invalid");
+    assert!(body["message"].as_str().unwrap().contains("not-an-email"));
+}
+
+/// Resolves `slow.example` after a minute and nothing but `example.com`.
+struct FakeDomainResolver;
+
+impl DomainResolver for FakeDomainResolver {
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, String>> {
+        Box::pin(async move {
+            if domain == "slow.example" {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            }
+            Ok(domain == "example.com")
+        })
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejects_domains_that_cannot_receive_email() {
+    // Arrange
+    let app = spawn_app_with_external_checks(ExternalChecks {
+        domain_resolver: Some(Arc::new(FakeDomainResolver)),
+        ..Default::default()
+    })
+    .await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let unknown = app
+        .post_subscriptions("name=le%20guin&email=ursula%40nowhere.example".into())
+        .await;
+    let known = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+    // Lookups that time out let the subscriber through.
+    let slow = app
+        .post_subscriptions("name=le%20guin&email=ursula%40slow.example".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, unknown.status().as_u16());
+    assert_eq!(200, known.status().as_u16());
+    assert_eq!(200, slow.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribers_get_emails_and_pages_in_the_language_their_browser_asks_for() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", "zh-TW,zh;q=0.9,en-US;q=0.8")
+        .body(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+            app.form_token()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "zh-TW");
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["personalizations"][0]["subject"], "歡迎訂閱！");
+
+    let confirmation_link = app.get_email_links(email_request).pop().unwrap();
+    let page = reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(r#"<html lang="zh-TW">"#));
+    assert!(page.contains("訂閱已確認"));
+}
+
+#[tokio::test]
+async fn a_locale_picked_on_the_form_wins_over_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/v3/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let picked = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", "en-US")
+        .body(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=zh-TW&form_token={}",
+            app.form_token()
+        ))
+        .send()
+        .await
+        .unwrap();
+    let unsupported = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com&locale=tlh".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, picked.status().as_u16());
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "zh-TW");
+    assert_eq!(400, unsupported.status().as_u16());
+}
+
+#[tokio::test]
+async fn validation_messages_are_worded_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=not-an-email&locale=zh-TW".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["fields"]["email"],
+        serde_json::json!({
+            "code": "invalid",
+            "email": "not-an-email",
+            "message": "not-an-email 不是有效的電子郵件地址。",
+        })
+    );
+}
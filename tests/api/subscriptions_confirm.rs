@@ -1,10 +1,10 @@
 // use reqwest::Url;
-// use wiremock::{
-//     matchers::{method, path},
-//     Mock, ResponseTemplate,
-// };
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, TestApp};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -20,6 +20,270 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
     assert_eq!(response.status().as_u16(), 400);
 }
 
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_records_the_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=marvinhsu&email=marvinhsu@gmail.com";
+
+    Mock::given(path("/v3/mail/send"))
+        .and(method("Post"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_email_links(email_request).pop().unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    let events = sqlx::query!("SELECT event_type FROM consent_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let events: Vec<_> = events.into_iter().map(|e| e.event_type).collect();
+    assert_eq!(events, vec!["requested", "confirmed"]);
+}
+
+/// Subscribes someone and returns the link of their confirmation email.
+async fn confirmation_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/v3/mail/send"))
+        .and(method("Post"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=marvinhsu&email=marvinhsu@gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_email_links(email_request).pop().unwrap()
+}
+
+#[tokio::test]
+async fn confirmation_pages_are_rendered_for_browsers() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = confirmation_link(&app).await;
+
+    // Act
+    let first = reqwest::get(confirmation_link.clone()).await.unwrap();
+    let second = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(first
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscription confirmed"));
+    assert_eq!(second.status().as_u16(), 200);
+    assert!(second.text().await.unwrap().contains("Already confirmed"));
+    let events = sqlx::query!("SELECT event_type FROM consent_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        events.len(),
+        2,
+        "Confirming twice records a single consent."
+    );
+}
+
+#[tokio::test]
+async fn concurrent_clicks_record_a_single_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = confirmation_link(&app).await;
+
+    // Act
+    let (first, second) = tokio::join!(
+        reqwest::get(confirmation_link.clone()),
+        reqwest::get(confirmation_link)
+    );
+
+    // Assert
+    assert_eq!(first.unwrap().status().as_u16(), 200);
+    assert_eq!(second.unwrap().status().as_u16(), 200);
+    let events = sqlx::query!("SELECT event_type FROM consent_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let events: Vec<_> = events.into_iter().map(|e| e.event_type).collect();
+    assert_eq!(events, vec!["requested", "confirmed"]);
+}
+
+#[tokio::test]
+async fn confirming_another_list_records_its_own_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO lists (id, slug, name, created_at) \
+        VALUES (gen_random_uuid(), 'releases', 'releases', now())"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    reqwest::get(confirmation_link(&app).await)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions("name=marvinhsu&email=marvinhsu@gmail.com&list=releases".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let releases_link = app.get_email_links(&email_request).pop().unwrap();
+
+    // Act
+    let response = reqwest::get(releases_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let memberships = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(memberships.iter().all(|m| m.status == "confirmed"));
+    let confirmations = sqlx::query!(
+        r#"
+SELECT lists.slug
+FROM consent_events JOIN lists ON lists.id = consent_events.list_id
+WHERE consent_events.event_type = 'confirmed'
+ORDER BY lists.slug
+"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let confirmations: Vec<_> = confirmations.into_iter().map(|c| c.slug).collect();
+    assert_eq!(confirmations, vec!["newsletter", "releases"]);
+}
+
+#[tokio::test]
+async fn api_clients_get_the_outcome_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = confirmation_link(&app).await;
+    let client = reqwest::Client::new();
+    let get = |url: reqwest::Url| client.get(url).header("Accept", "application/json").send();
+
+    // Act
+    let confirmed = get(confirmation_link.clone()).await.unwrap();
+    let already_confirmed = get(confirmation_link.clone()).await.unwrap();
+    let mut unknown_link = confirmation_link;
+    unknown_link.set_query(Some("subscription_token=unknown"));
+    let invalid = get(unknown_link).await.unwrap();
+
+    // Assert
+    for (response, status, code) in [
+        (confirmed, 200, "confirmed"),
+        (already_confirmed, 200, "already_confirmed"),
+        (invalid, 401, "invalid_token"),
+    ] {
+        assert_eq!(response.status().as_u16(), status);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], code);
+        assert!(body["message"].as_str().is_some_and(|m| !m.is_empty()));
+    }
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = confirmation_link(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let page = reqwest::get(confirmation_link.clone()).await.unwrap();
+    let json = reqwest::Client::new()
+        .get(confirmation_link)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 410);
+    assert!(page.text().await.unwrap().contains("/subscriptions"));
+    assert_eq!(json.status().as_u16(), 410);
+    let body: serde_json::Value = json.json().await.unwrap();
+    assert_eq!(body["status"], "expired_token");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn unknown_tokens_get_a_401_page() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.text().await.unwrap().contains("Invalid link"));
+}
+
+#[tokio::test]
+async fn a_fatal_database_error_gets_a_500_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = confirmation_link(&app).await;
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+}
+
 // #[tokio::test]
 // async fn the_link_return_by_subscribe_returns_a_200_if_called() {
 //     let app = spawn_app().await;
@@ -0,0 +1,307 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+#[tokio::test]
+async fn preferences_are_rejected_without_a_valid_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/preferences?subscription_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn preferences_are_returned_as_json_when_asked_for() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/subscriptions/preferences?subscription_token={}",
+            app.address, token
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let preferences: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preferences["name"], "hsu marvin");
+    assert_eq!(preferences["delivery_cadence"], "immediate");
+    assert_eq!(preferences["lists"][0]["slug"], "newsletter");
+}
+
+#[tokio::test]
+async fn name_and_cadence_changes_are_applied_and_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+
+    // Act
+    let response = app
+        .post_preferences(serde_json::json!({
+            "subscription_token": token,
+            "name": "marvin",
+            "delivery_cadence": "weekly",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name, delivery_cadence FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "marvin");
+    assert_eq!(saved.delivery_cadence, "weekly");
+    let changes =
+        sqlx::query!("SELECT field, old_value, new_value FROM preference_changes ORDER BY field")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].field, "delivery_cadence");
+    assert_eq!(changes[0].old_value.as_deref(), Some("immediate"));
+    assert_eq!(changes[1].field, "name");
+    assert_eq!(changes[1].new_value.as_deref(), Some("marvin"));
+}
+
+#[tokio::test]
+async fn invalid_changes_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+    let test_cases = vec![
+        (serde_json::json!({"name": "(marvin)"}), "invalid name"),
+        (
+            serde_json::json!({"email": "not-an-email"}),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({"delivery_cadence": "monthly"}),
+            "unknown cadence",
+        ),
+        (serde_json::json!({"lists": ["not-a-list"]}), "unknown list"),
+        (
+            serde_json::json!({"email": "marvin@gmial.com"}),
+            "undeliverable email",
+        ),
+    ];
+
+    for (mut body, description) in test_cases {
+        body["subscription_token"] = token.clone().into();
+
+        // Act
+        let response = app.post_preferences(body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had an {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn changes_are_refused_in_the_language_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+    sqlx::query!("UPDATE subscriptions SET locale = 'zh-TW'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_preferences(serde_json::json!({
+            "subscription_token": token,
+            "lists": ["not-a-list"],
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "not-a-list 不是現有的訂閱清單。");
+}
+
+#[tokio::test]
+async fn email_changes_take_effect_once_the_new_address_is_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Request the change
+    app.post_preferences(serde_json::json!({
+        "subscription_token": token,
+        "email": "marvin@example.com",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert - Part 1
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "marvin_hsu@gmail.com");
+
+    // Act - Part 2 - Follow the link sent to the new address
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["personalizations"][0]["to"][0]["email"],
+        "marvin@example.com"
+    );
+    let confirmation_link = app.get_email_links(&email_request).pop().unwrap();
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert - Part 2
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("We will write to marvin@example.com from now on."));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "marvin@example.com");
+    let change = sqlx::query!("SELECT field, old_value, new_value FROM preference_changes")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(change.field, "email");
+    assert_eq!(change.old_value.as_deref(), Some("marvin_hsu@gmail.com"));
+}
+
+#[tokio::test]
+async fn only_the_case_of_the_domain_is_not_an_email_change() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+    Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for email in ["marvin_hsu@GMAIL.com", "Marvin_Hsu@gmail.com"] {
+        app.post_preferences(serde_json::json!({
+            "subscription_token": token,
+            "email": email,
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+
+    // Assert
+    let requested = sqlx::query!("SELECT new_email FROM email_change_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(requested.len(), 1);
+    assert_eq!(requested[0].new_email, "Marvin_Hsu@gmail.com");
+}
+
+#[tokio::test]
+async fn topics_can_be_joined_and_left() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app).await;
+    sqlx::query!(
+        "INSERT INTO lists (id, slug, name, created_at) VALUES (gen_random_uuid(), 'releases', 'Releases', now())"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_preferences(serde_json::json!({
+        "subscription_token": token,
+        "lists": ["releases"],
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let memberships = sqlx::query!(
+        r#"
+SELECT lists.slug, list_memberships.status
+FROM list_memberships JOIN lists ON lists.id = list_memberships.list_id
+ORDER BY lists.slug
+"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(memberships[0].slug, "newsletter");
+    assert_eq!(memberships[0].status, "unsubscribed");
+    assert_eq!(memberships[1].slug, "releases");
+    assert_eq!(memberships[1].status, "confirmed");
+}
+
+/// Subscribe, confirm and return the token sent in the confirmation email.
+async fn subscribe(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/v3/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=hsu%20marvin&email=marvin_hsu%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_email_links(email_request).pop().unwrap();
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let token = confirmation_link
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    token
+}